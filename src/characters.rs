@@ -0,0 +1,495 @@
+use crate::toml_helpers::*;
+use crate::*;
+use fe_data::FeError;
+use imgui::*;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::*;
+use uuid::Uuid;
+
+pub type Portraits = HashMap<PathBuf, TextureId>;
+
+/// A named character who can be placed on any number of maps.
+/// Units refer to characters by UUID, so changes to a character apply to every map.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Character {
+	pub name: String,
+	pub uuid: Uuid,
+	pub desc: String,
+	pub portrait: Option<PathBuf>,
+	/// The class given to units when they're assigned this character.
+	pub class: Option<Uuid>,
+	/// The character's own base stats, which replace those of their class.
+	pub bases: Stats,
+	/// The character's own growth rates, as percentages.
+	pub growths: Stats,
+}
+
+impl Character {
+	pub fn new() -> Self {
+		Self {
+			name: String::new(),
+			uuid: Uuid::new_v4(),
+			desc: String::new(),
+			portrait: None,
+			class: None,
+			bases: ClassStats::new().bases,
+			growths: Stats([0; STAT_COUNT]),
+		}
+	}
+
+	pub fn from_table(table: &Table) -> Result<Self, Box<dyn Error>> {
+		let name = get_string(table, "name")?;
+		let parse_uuid = |key: &str| -> Result<Uuid, Box<dyn Error>> {
+			match Uuid::parse_str(&get_string(table, key)?) {
+				Ok(uuid) => Ok(uuid),
+				Err(msg) => Err(FeError::from(format!("{name}: Invalid {key}: {msg}")))?,
+			}
+		};
+
+		let mut character = Self {
+			name: name.clone(),
+			uuid: parse_uuid("uuid")?,
+			..Self::new()
+		};
+		if table.contains_key("desc") {
+			character.desc = get_string(table, "desc")?;
+		}
+		if table.contains_key("portrait") {
+			character.portrait = Some(PathBuf::from(get_string(table, "portrait")?));
+		}
+		if table.contains_key("class") {
+			character.class = Some(parse_uuid("class")?);
+		}
+		if table.contains_key("bases") {
+			character.bases = Stats::from_table(get_table(table, "bases")?)?;
+		}
+		if table.contains_key("growths") {
+			character.growths = Stats::from_table(get_table(table, "growths")?)?;
+		}
+		for growth in &mut character.growths.0 {
+			*growth = (*growth).min(MAX_GROWTH);
+		}
+		Ok(character)
+	}
+
+	pub fn to_table(&self) -> Table {
+		let mut table = Table::new();
+		table.insert("name".into(), Value::String(self.name.clone()));
+		table.insert("uuid".into(), Value::String(self.uuid.to_string()));
+		table.insert("desc".into(), Value::String(self.desc.clone()));
+		if let Some(portrait) = &self.portrait {
+			table.insert(
+				"portrait".into(),
+				Value::String(portrait.to_string_lossy().to_string()),
+			);
+		}
+		if let Some(class) = self.class {
+			table.insert("class".into(), Value::String(class.to_string()));
+		}
+		table.insert("bases".into(), Value::Table(self.bases.to_table()));
+		table.insert("growths".into(), Value::Table(self.growths.to_table()));
+		table
+	}
+
+	/// Draws the character's fields.
+	/// Returns the character's previous state if an edit was completed this frame.
+	fn editor(
+		&mut self,
+		ui: &Ui,
+		portraits: &Portraits,
+		missing: &MissingTextures,
+		classes: &[ClassEntry],
+		edit_start: &mut Option<Character>,
+	) -> Option<Character> {
+		let before = self.clone();
+		let mut edit = None;
+
+		ui.input_text("##name", &mut self.name).hint("Name").build();
+		edit = edit.or(track_edit(ui, &before, edit_start));
+		let clicked = match &self.portrait {
+			Some(portrait) => {
+				ui.image_button("##portrait", missing.icon(portraits, portrait), [64.0; 2])
+			}
+			None => ui.button_with_size("?##portrait", [64.0; 2]),
+		};
+		if clicked {
+			ui.open_popup("Select Portrait");
+		}
+		ui.hover_tooltip("Click to select portrait");
+
+		let mut names = vec!["None"];
+		names.extend(classes.iter().map(|c| c.data.name.as_str()));
+		let mut selected = match self.class {
+			Some(uuid) => match classes.iter().position(|c| c.uuid == uuid) {
+				Some(i) => i + 1,
+				None => {
+					// Keep classes which have been deleted selected, rather than silently changing them.
+					names.push("Missing class");
+					names.len() - 1
+				}
+			},
+			None => 0,
+		};
+		if ui.combo_simple_string("Class", &mut selected, &names) && selected <= classes.len() {
+			self.class = selected.checked_sub(1).map(|i| classes[i].uuid);
+			edit = Some(before.clone());
+		}
+		ui.hover_tooltip("The class given to units when they're assigned this character");
+
+		ui.text("Description:");
+		ui.input_text_multiline(
+			"##desc",
+			&mut self.desc,
+			[ui.content_region_avail()[0], 64.0],
+		)
+		.build();
+		edit = edit.or(track_edit(ui, &before, edit_start));
+
+		ui.columns(3, "##stats", false);
+		for header in ["Stat", "Base", "Growth %"] {
+			ui.text(header);
+			ui.next_column();
+		}
+		for (i, name) in STAT_NAMES.iter().enumerate() {
+			let _id = ui.push_id_usize(i);
+			ui.text(name);
+			for (label, stats, max) in [
+				("##base", &mut self.bases, MAX_STAT),
+				("##growth", &mut self.growths, MAX_GROWTH),
+			] {
+				ui.next_column();
+				ui.set_next_item_width(-1.0);
+				ui.input_int(label, &mut stats.0[i]).step(0).build();
+				stats.0[i] = stats.0[i].clamp(0, max);
+				edit = edit.or(track_edit(ui, &before, edit_start));
+			}
+			ui.next_column();
+		}
+		ui.columns(1, "##stats", false);
+
+		ui.popup("Select Portrait", || {
+			ui.text("Select a portrait");
+			if portraits.is_empty() {
+				ui.text_disabled("There are no images in portraits.");
+			}
+			for (i, (path, texture)) in portraits.iter().enumerate() {
+				// Portraits per row.
+				if i % 3 != 0 {
+					ui.same_line();
+				}
+				if ui.image_button(i.to_string(), *texture, [64.0; 2]) {
+					edit = Some(self.clone());
+					self.portrait = Some(path.clone());
+				}
+				ui.hover_tooltip(&path.to_string_lossy());
+			}
+		});
+
+		edit
+	}
+}
+
+/// A reversible change to the character roster.
+#[derive(Debug)]
+pub enum CharacterEdit {
+	Insert(usize, Character),
+	Remove(usize, Character),
+	Modify {
+		index: usize,
+		from: Character,
+		to: Character,
+	},
+}
+
+impl CharacterEdit {
+	pub fn apply(&self, characters: &mut Vec<Character>) {
+		match self {
+			CharacterEdit::Insert(index, character) => characters.insert(*index, character.clone()),
+			CharacterEdit::Remove(index, _) => {
+				characters.remove(*index);
+			}
+			CharacterEdit::Modify { index, to, .. } => characters[*index] = to.clone(),
+		}
+	}
+
+	pub fn revert(&self, characters: &mut Vec<Character>) {
+		match self {
+			CharacterEdit::Insert(index, _) => {
+				characters.remove(*index);
+			}
+			CharacterEdit::Remove(index, character) => characters.insert(*index, character.clone()),
+			CharacterEdit::Modify { index, from, .. } => characters[*index] = from.clone(),
+		}
+	}
+}
+
+pub struct CharacterEditor {
+	pub save_state: SaveState,
+	pub characters: Vec<Character>,
+	pub history: History<CharacterEdit>,
+	/// Whether the character window is focused this frame; used to direct undo and redo.
+	pub focused: bool,
+	pub search_field: String,
+	/// The character's state when the current text edit began.
+	edit_start: Option<Character>,
+	/// A character to open and scroll to on the next frame.
+	reveal: Option<Uuid>,
+}
+
+impl CharacterEditor {
+	/// Opens a character file, or starts with an empty roster if it doesn't exist.
+	pub fn open(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+		let mut characters = Vec::new();
+
+		if let Ok(toml) = fs::read_to_string(path) {
+			let toml: Table = toml.parse()?;
+			if toml.contains_key("character") {
+				for character in get_array(&toml, "character")? {
+					let Value::Table(character) = character else {
+						Err(FeError::from("Character is not a table"))?
+					};
+					let character = Character::from_table(character)?;
+					if characters
+						.iter()
+						.any(|c: &Character| c.uuid == character.uuid)
+					{
+						Err(FeError::from(format!(
+							"{} has the same UUID as another character",
+							character.name
+						)))?;
+					}
+					characters.push(character);
+				}
+			}
+		}
+
+		Ok(Self {
+			save_state: SaveState::new(),
+			characters,
+			history: History::new(),
+			focused: false,
+			search_field: String::new(),
+			edit_start: None,
+			reveal: None,
+		})
+	}
+
+	pub fn to_toml(&self) -> Result<String, Box<dyn Error>> {
+		let mut characters = Vec::new();
+		for character in &self.characters {
+			if character.name.is_empty() {
+				Err(FeError::from("Characters must have a name"))?;
+			}
+			characters.push(Value::Table(character.to_table()));
+		}
+
+		let mut toml = Table::new();
+		toml.insert("character".into(), Value::Array(characters));
+		Ok(toml::to_string(&toml)?)
+	}
+
+	/// Looks up a character by its UUID.
+	pub fn get(&self, uuid: Uuid) -> Option<&Character> {
+		self.characters.iter().find(|c| c.uuid == uuid)
+	}
+
+	fn push(&mut self, edit: CharacterEdit) {
+		self.history.push(edit);
+		self.save_state.sync_with(&self.history);
+	}
+
+	fn apply(&mut self, edit: CharacterEdit) {
+		edit.apply(&mut self.characters);
+		self.push(edit);
+	}
+
+	pub fn undo(&mut self) {
+		self.edit_start = None;
+		if let Some(edit) = self.history.undo() {
+			edit.revert(&mut self.characters);
+			self.save_state.sync_with(&self.history);
+		}
+	}
+
+	pub fn redo(&mut self) {
+		self.edit_start = None;
+		if let Some(edit) = self.history.redo() {
+			edit.apply(&mut self.characters);
+			self.save_state.sync_with(&self.history);
+		}
+	}
+
+	pub fn mark_saved(&mut self) {
+		self.save_state.mark_saved();
+		self.history.mark_saved();
+	}
+
+	/// Opens a character's entry and scrolls to it, such as to show a problem with it.
+	pub fn reveal(&mut self, uuid: Uuid) {
+		self.search_field.clear();
+		self.reveal = Some(uuid);
+	}
+
+	pub fn draw(
+		&mut self,
+		ui: &Ui,
+		position: (f32, f32),
+		portraits: &Portraits,
+		missing: &MissingTextures,
+		classes: &[ClassEntry],
+	) {
+		self.focused = false;
+		let reveal = self.reveal.take();
+		let mut window = ui
+			.window("Characters")
+			.position([position.0, position.1], Condition::FirstUseEver)
+			.size([250.0, 400.0], Condition::FirstUseEver)
+			.focus_on_appearing(false)
+			.collapsed(true, Condition::FirstUseEver)
+			.unsaved_document(!self.save_state.is_saved());
+		if reveal.is_some() {
+			window = window.collapsed(false, Condition::Always).focused(true);
+		}
+		window.build(|| {
+			self.focused =
+				ui.is_window_focused_with_flags(WindowFocusedFlags::ROOT_AND_CHILD_WINDOWS);
+
+			ui.text("Search:");
+			ui.input_text("##search", &mut self.search_field).build();
+
+			ui.separator();
+
+			let query = self.search_field.to_ascii_lowercase();
+			let mut edits = Vec::new();
+			for (index, character) in self.characters.iter_mut().enumerate() {
+				if !character.name.to_ascii_lowercase().contains(&query) {
+					continue;
+				}
+
+				let _id = ui.push_id(character.uuid.to_string());
+
+				let revealed = reveal == Some(character.uuid);
+				if revealed {
+					ui.set_scroll_here_y();
+				}
+
+				let label = if character.name.is_empty() {
+					String::from("New character")
+				} else {
+					character.name.clone()
+				};
+				ui.tree_node_config("##header")
+					.label::<String, String>(label)
+					.framed(true)
+					.opened(
+						character.name.is_empty() || revealed,
+						if revealed {
+							Condition::Always
+						} else {
+							Condition::FirstUseEver
+						},
+					)
+					.build(|| {
+						if let Some(from) =
+							character.editor(ui, portraits, missing, classes, &mut self.edit_start)
+						{
+							edits.push(CharacterEdit::Modify {
+								index,
+								from,
+								to: character.clone(),
+							});
+						}
+						if ui.button("Delete") {
+							ui.open_popup("Delete");
+						}
+
+						ui.modal_popup_config("Delete").build(|| {
+							ui.text(format!(
+								"Do you really want to delete \"{}\"?",
+								character.name
+							));
+							ui.text("Units on maps will no longer be this character.");
+							if ui.button("Cancel") {
+								ui.close_current_popup();
+							}
+							ui.same_line();
+							if ui.button("Delete") {
+								ui.close_current_popup();
+								edits.push(CharacterEdit::Remove(index, character.clone()));
+							}
+						});
+					});
+
+				ui.separator();
+			}
+
+			for edit in edits {
+				// Modifications have already been made by the UI.
+				if let CharacterEdit::Remove(..) = edit {
+					self.apply(edit);
+				} else {
+					self.push(edit);
+				}
+			}
+
+			if ui.button("Create new character") {
+				self.apply(CharacterEdit::Insert(
+					self.characters.len(),
+					Character::new(),
+				));
+			}
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_helpers::TempFile;
+
+	#[test]
+	fn characters_survive_saving() {
+		let file = TempFile::new();
+		let mut editor = CharacterEditor::open(&file.0).unwrap();
+		assert!(editor.characters.is_empty());
+		let mut growths = Stats([0; STAT_COUNT]);
+		growths.0[0] = 80;
+		let hero = Character {
+			name: String::from("Eirika"),
+			desc: String::from("Princess of Renais."),
+			portrait: Some(PathBuf::from("eirika.png")),
+			class: Some(Uuid::new_v4()),
+			growths,
+			..Character::new()
+		};
+		editor.characters = vec![
+			hero,
+			Character {
+				name: String::from("Seth"),
+				..Character::new()
+			},
+		];
+
+		fs::write(&file.0, editor.to_toml().unwrap()).unwrap();
+		// Units refer to characters by UUID, so they have to be kept.
+		assert_eq!(
+			CharacterEditor::open(&file.0).unwrap().characters,
+			editor.characters
+		);
+	}
+
+	#[test]
+	fn characters_must_have_different_uuids() {
+		let file = TempFile::new();
+		let mut editor = CharacterEditor::open(&file.0).unwrap();
+		let mut character = Character::new();
+		character.name = String::from("Seth");
+		editor.characters = vec![character.clone(), character];
+
+		fs::write(&file.0, editor.to_toml().unwrap()).unwrap();
+		assert!(CharacterEditor::open(&file.0).is_err());
+	}
+}
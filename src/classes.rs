@@ -13,19 +13,60 @@ use uuid::Uuid;
 use std::hash::Hash;
 pub type ClassIcons = HashMap<PathBuf, TextureId>;
 
+/// The size of each class in the promotion graph.
+const GRAPH_NODE_SIZE: [f32; 2] = [120.0, 40.0];
+/// The space between classes in the promotion graph.
+const GRAPH_SPACING: [f32; 2] = [60.0, 16.0];
+const CYCLE_COLOR: [f32; 4] = [1.0, 0.4, 0.4, 1.0];
+
+/// The rest of the project, which classes' movement, weapons, and promotions refer to.
+struct ClassReferences<'a> {
+	/// The UUID and name of every class,
+	/// since the classes themselves can't be borrowed while one of them is being edited.
+	classes: Vec<(Uuid, String)>,
+	/// Classes which can promote back into themselves.
+	cycles: Vec<Uuid>,
+	movement_classes: &'a [String],
+	items: &'a [Item],
+}
+
 #[derive(Hash)]
 pub struct ClassEntry {
 	// Data
 	pub data: ClassData,
+	pub stats: ClassStats,
+	pub progression: ClassProgression,
 	pub uuid: Uuid,
 	pub is_open: bool,
 }
 
 impl ClassEntry {
-	pub fn from(name: String, table: Table) -> Result<Self, Box<dyn Error>> {
+	pub fn from(name: String, mut table: Table) -> Result<Self, Box<dyn Error>> {
+		// Stats which break the class's own rules are reported by `lint_classes` instead,
+		// so that one bad class doesn't stop the rest from loading.
+		let stats = match ClassStats::take_from(&mut table) {
+			Ok(stats) => stats,
+			Err(msg) => Err(FeError::from(format!("{name}: {msg}")))?,
+		};
+		let progression = match ClassProgression::take_from(&mut table) {
+			Ok(progression) => progression,
+			Err(msg) => Err(FeError::from(format!("{name}: {msg}")))?,
+		};
+		// Map units refer to classes by UUID, so it needs to be saved.
+		// Classes from before this was the case are given a new one.
+		let uuid = match table.remove("uuid") {
+			Some(Value::String(uuid)) => match Uuid::parse_str(&uuid) {
+				Ok(uuid) => uuid,
+				Err(msg) => Err(FeError::from(format!("{name}: Invalid UUID: {msg}")))?,
+			},
+			Some(_) => Err(FeError::from(format!("{name}: UUID is not a string")))?,
+			None => Uuid::new_v4(),
+		};
 		Ok(Self {
 			data: ClassData::from(name, table)?,
-			uuid: Uuid::new_v4(),
+			stats,
+			progression,
+			uuid,
 			is_open: true,
 		})
 	}
@@ -33,14 +74,32 @@ impl ClassEntry {
 	pub fn with_texture(texture: PathBuf) -> Self {
 		Self {
 			data: ClassData::with_texture(texture),
+			stats: ClassStats::new(),
+			progression: ClassProgression::new(),
 			uuid: Uuid::new_v4(),
 			is_open: true,
 		}
 	}
 
-	fn editor(&mut self, ui: &Ui, class_icons: &ClassIcons) {
-		ui.input_text("##name", &mut self.data.name).hint("Name").build();
-		if ui.image_button("##class", class_icons[&self.data.texture], [32.0, 32.0]) {
+	/// Draws the entry's fields.
+	/// Returns the class's previous state if an edit was completed this frame.
+	fn editor(
+		&mut self,
+		ui: &Ui,
+		class_icons: &ClassIcons,
+		missing: &MissingTextures,
+		references: &ClassReferences,
+		edit_start: &mut Option<ClassState>,
+	) -> Option<ClassState> {
+		let before = ClassState::of(self);
+		let mut edit = None;
+
+		ui.input_text("##name", &mut self.data.name)
+			.hint("Name")
+			.build();
+		edit = edit.or(track_edit(ui, &before, edit_start));
+		let icon = missing.icon(class_icons, &self.data.texture);
+		if ui.image_button("##class", icon, [32.0, 32.0]) {
 			ui.open_popup("Select Icon");
 		}
 		ui.hover_tooltip("Click to select class icon");
@@ -52,6 +111,36 @@ impl ClassEntry {
 			[ui.content_region_avail()[0], 64.0],
 		)
 		.build();
+		edit = edit.or(track_edit(ui, &before, edit_start));
+
+		ui.columns(4, "##stats", false);
+		for header in ["Stat", "Base", "Growth %", "Cap"] {
+			ui.text(header);
+			ui.next_column();
+		}
+		for (i, name) in STAT_NAMES.iter().enumerate() {
+			let _id = ui.push_id_usize(i);
+			ui.text(name);
+			for (label, stats, max) in [
+				("##base", &mut self.stats.bases, MAX_STAT),
+				("##growth", &mut self.stats.growths, MAX_GROWTH),
+				("##cap", &mut self.stats.caps, MAX_STAT),
+			] {
+				ui.next_column();
+				ui.set_next_item_width(-1.0);
+				ui.input_int(label, &mut stats.0[i]).step(0).build();
+				stats.0[i] = stats.0[i].clamp(0, max);
+				edit = edit.or(track_edit(ui, &before, edit_start));
+			}
+			ui.next_column();
+		}
+		ui.columns(1, "##stats", false);
+		// Bases are allowed to exceed caps while editing, since caps may be changed next.
+		if let Err(msg) = self.stats.validate() {
+			ui.text_colored([1.0, 0.4, 0.4, 1.0], msg);
+		}
+
+		edit = edit.or(self.progression_editor(ui, references, &before, edit_start));
 
 		ui.popup("Select Icon", || {
 			ui.text("Select an icon");
@@ -60,16 +149,159 @@ impl ClassEntry {
 				if i % 3 != 0 {
 					ui.same_line();
 				}
-				if ui.image_button(
-					i.to_string(),
-					*texture,
-					[32.0; 2]
-				) {
+				if ui.image_button(i.to_string(), *texture, [32.0; 2]) {
+					edit = Some(ClassState::of(self));
 					self.data.texture = path.clone();
 				}
 				ui.hover_tooltip(&path.to_string_lossy());
 			}
 		});
+
+		edit
+	}
+
+	/// Draws the class's movement class, weapons, and promotions.
+	/// Returns the class's previous state if an edit was completed this frame.
+	fn progression_editor(
+		&mut self,
+		ui: &Ui,
+		references: &ClassReferences,
+		before: &ClassState,
+		edit_start: &mut Option<ClassState>,
+	) -> Option<ClassState> {
+		let mut edit = None;
+		let progression = &mut self.progression;
+
+		ui.separator();
+		let mut names = vec!["None"];
+		names.extend(references.movement_classes.iter().map(|c| c.as_str()));
+		let mut selected = if progression.movement.is_empty() {
+			0
+		} else {
+			match references
+				.movement_classes
+				.iter()
+				.position(|c| *c == progression.movement)
+			{
+				Some(i) => i + 1,
+				// Keep movement classes which were renamed or deleted selected.
+				None => {
+					names.push(progression.movement.as_str());
+					names.len() - 1
+				}
+			}
+		};
+		if ui.combo_simple_string("Movement", &mut selected, &names)
+			&& selected <= references.movement_classes.len()
+		{
+			progression.movement = match selected.checked_sub(1) {
+				Some(i) => references.movement_classes[i].clone(),
+				None => String::new(),
+			};
+			edit = Some(before.clone());
+		}
+		ui.hover_tooltip("Movement classes are listed in the terrain editor");
+
+		ui.text("Weapons:");
+		let ranks = WeaponRank::ALL.map(|r| r.name());
+		for weapon_type in WeaponType::ALL {
+			let _id = ui.push_id(weapon_type.name());
+			let mut usable = progression.weapons.contains_key(&weapon_type);
+			if ui.checkbox(weapon_type.name(), &mut usable) {
+				if usable {
+					progression.weapons.insert(weapon_type, WeaponRank::E);
+				} else {
+					progression.weapons.remove(&weapon_type);
+				}
+				edit = Some(before.clone());
+			}
+			if let Some(rank) = progression.weapons.get_mut(&weapon_type) {
+				ui.same_line_with_pos(100.0);
+				ui.set_next_item_width(48.0);
+				let mut selected = *rank as usize;
+				if ui.combo_simple_string("##rank", &mut selected, &ranks) {
+					*rank = WeaponRank::ALL[selected];
+					edit = Some(before.clone());
+				}
+				ui.hover_tooltip("Starting rank");
+			}
+		}
+
+		ui.text("Promotions:");
+		// Classes can't promote into themselves.
+		let targets: Vec<&(Uuid, String)> = references
+			.classes
+			.iter()
+			.filter(|(uuid, _)| *uuid != self.uuid)
+			.collect();
+		let mut remove = None;
+		for (i, promotion) in progression.promotions.iter_mut().enumerate() {
+			let _id = ui.push_id_usize(i);
+
+			let mut names: Vec<&str> = targets.iter().map(|(_, name)| name.as_str()).collect();
+			let mut selected = match targets
+				.iter()
+				.position(|(uuid, _)| *uuid == promotion.class)
+			{
+				Some(i) => i,
+				// The class was deleted after the promotion was added.
+				None => {
+					names.push("Missing class");
+					names.len() - 1
+				}
+			};
+			if ui.combo_simple_string("Class", &mut selected, &names) && selected < targets.len() {
+				promotion.class = targets[selected].0;
+				edit = Some(before.clone());
+			}
+
+			ui.input_scalar("Level", &mut promotion.level)
+				.step(1)
+				.build();
+			promotion.level = promotion.level.clamp(1, MAX_LEVEL);
+			edit = edit.or(track_edit(ui, before, edit_start));
+
+			let mut names = vec!["None"];
+			names.extend(references.items.iter().map(|i| i.name.as_str()));
+			let missing = format!("Missing item ({})", promotion.item.unwrap_or_default());
+			let mut selected = match promotion.item {
+				Some(item) => match references.items.iter().position(|i| i.uuid == item) {
+					Some(i) => i + 1,
+					None => {
+						names.push(&missing);
+						names.len() - 1
+					}
+				},
+				None => 0,
+			};
+			if ui.combo_simple_string("Item", &mut selected, &names)
+				&& selected <= references.items.len()
+			{
+				promotion.item = selected.checked_sub(1).map(|i| references.items[i].uuid);
+				edit = Some(before.clone());
+			}
+			ui.hover_tooltip("The item needed to promote, if any");
+
+			if ui.button("Remove Promotion") {
+				remove = Some(i);
+			}
+			ui.separator();
+		}
+		if let Some(i) = remove {
+			progression.promotions.remove(i);
+			edit = Some(before.clone());
+		}
+		if let Some((uuid, _)) = targets.first() {
+			if ui.button("Add Promotion") {
+				progression.promotions.push(Promotion::new(*uuid));
+				edit = Some(before.clone());
+			}
+		}
+		if references.cycles.contains(&self.uuid) {
+			ui.text_colored(CYCLE_COLOR, "This class can promote back into itself.");
+		}
+
+		edit
 	}
 
 	fn close(&mut self) {
@@ -81,11 +313,96 @@ impl ClassEntry {
 	}
 }
 
+/// A copy of a class's fields, used to restore classes which have been changed or deleted.
+#[derive(Clone, Debug)]
+pub struct ClassState {
+	pub name: String,
+	pub desc: String,
+	pub texture: PathBuf,
+	pub stats: ClassStats,
+	pub progression: ClassProgression,
+}
+
+impl ClassState {
+	pub fn of(entry: &ClassEntry) -> Self {
+		Self {
+			name: entry.data.name.clone(),
+			desc: entry.data.desc.clone(),
+			texture: entry.data.texture.clone(),
+			stats: entry.stats,
+			progression: entry.progression.clone(),
+		}
+	}
+
+	pub fn to_entry(&self, uuid: Uuid) -> ClassEntry {
+		let mut entry = ClassEntry {
+			data: ClassData::with_texture(self.texture.clone()),
+			stats: self.stats,
+			progression: self.progression.clone(),
+			uuid,
+			is_open: true,
+		};
+		self.write_to(&mut entry);
+		entry
+	}
+
+	pub fn write_to(&self, entry: &mut ClassEntry) {
+		entry.data.name = self.name.clone();
+		entry.data.desc = self.desc.clone();
+		entry.data.texture = self.texture.clone();
+		entry.stats = self.stats;
+		entry.progression = self.progression.clone();
+	}
+}
+
+/// A reversible change to the class list.
+/// Classes keep their UUID when they are restored.
+#[derive(Debug)]
+pub enum ClassEdit {
+	Insert(usize, Uuid, ClassState),
+	Remove(usize, Uuid, ClassState),
+	/// The states are boxed, since they're much larger than the other edits.
+	Modify {
+		index: usize,
+		from: Box<ClassState>,
+		to: Box<ClassState>,
+	},
+}
+
+impl ClassEdit {
+	pub fn apply(&self, classes: &mut Vec<ClassEntry>) {
+		match self {
+			ClassEdit::Insert(index, uuid, class) => classes.insert(*index, class.to_entry(*uuid)),
+			ClassEdit::Remove(index, _, _) => {
+				classes.remove(*index);
+			}
+			ClassEdit::Modify { index, to, .. } => to.write_to(&mut classes[*index]),
+		}
+	}
+
+	pub fn revert(&self, classes: &mut Vec<ClassEntry>) {
+		match self {
+			ClassEdit::Insert(index, _, _) => {
+				classes.remove(*index);
+			}
+			ClassEdit::Remove(index, uuid, class) => classes.insert(*index, class.to_entry(*uuid)),
+			ClassEdit::Modify { index, from, .. } => from.write_to(&mut classes[*index]),
+		}
+	}
+}
+
 pub struct ClassEditor {
-	pub unsaved: bool,
+	pub save_state: SaveState,
 	pub classes: Vec<ClassEntry>,
+	pub history: History<ClassEdit>,
+	/// Whether the class window is focused this frame; used to direct undo and redo.
+	pub focused: bool,
 	pub search_field: String,
 	pub default_icon: PathBuf,
+	/// The class's state when the current text edit began.
+	edit_start: Option<ClassState>,
+	/// A class to open and scroll to on the next frame.
+	reveal: Option<Uuid>,
 }
 
 impl ClassEditor {
@@ -100,120 +417,416 @@ impl ClassEditor {
 				} else {
 					Err(FeError::from("Class data is not a table"))?
 				};
+				if classes.iter().any(|c: &ClassEntry| c.uuid == class.uuid) {
+					Err(FeError::from(format!(
+						"{} has the same UUID as another class",
+						class.data.name
+					)))?;
+				}
 				classes.push(class);
 			}
 		}
 
 		Ok(Self {
-			unsaved: false,
+			save_state: SaveState::new(),
 			classes,
+			history: History::new(),
+			focused: false,
 			search_field: String::new(),
 			default_icon,
+			edit_start: None,
+			reveal: None,
 		})
 	}
 
 	pub fn to_toml(&self) -> Result<String, Box<dyn Error>> {
 		let mut toml = String::new();
-		for i in &self.classes {
-			toml += &i.data.to_toml()?;
+		for (index, i) in self.classes.iter().enumerate() {
+			// Each class is a table named after it, so a repeated name would make the file invalid.
+			if self.classes[..index]
+				.iter()
+				.any(|other| other.data.name == i.data.name)
+			{
+				Err(FeError::from(format!(
+					"More than one class is named \"{}\"",
+					i.data.name
+				)))?;
+			}
+			// Stats, progression and UUIDs aren't part of ClassData, so add them to its table.
+			let mut class: Table = i.data.to_toml()?.parse()?;
+			match class.get_mut(&i.data.name) {
+				Some(Value::Table(table)) => {
+					table.insert("uuid".into(), Value::String(i.uuid.to_string()));
+					i.stats.write_to(table);
+					i.progression.write_to(table);
+				}
+				_ => Err(FeError::from(format!(
+					"Failed to write stats for {}",
+					i.data.name
+				)))?,
+			}
+			toml += &toml::to_string(&class)?;
 			toml += "\n";
 		}
 		Ok(toml)
 	}
 
+	/// Looks up a class by its UUID.
+	pub fn get(&self, uuid: Uuid) -> Option<&ClassEntry> {
+		self.classes.iter().find(|c| c.uuid == uuid)
+	}
+
 	fn add_entry(&mut self) {
-		self.classes
-			.push(ClassEntry::with_texture(self.default_icon.clone()));
+		let entry = ClassEntry::with_texture(self.default_icon.clone());
+		self.history.push(ClassEdit::Insert(
+			self.classes.len(),
+			entry.uuid,
+			ClassState::of(&entry),
+		));
+		self.classes.push(entry);
+	}
+
+	pub fn undo(&mut self) {
+		self.edit_start = None;
+		if let Some(edit) = self.history.undo() {
+			edit.revert(&mut self.classes);
+			self.save_state.sync_with(&self.history);
+		}
+	}
+
+	pub fn redo(&mut self) {
+		self.edit_start = None;
+		if let Some(edit) = self.history.redo() {
+			edit.apply(&mut self.classes);
+			self.save_state.sync_with(&self.history);
+		}
+	}
+
+	pub fn mark_saved(&mut self) {
+		self.save_state.mark_saved();
+		self.history.mark_saved();
+	}
+
+	/// Opens a class's entry and scrolls to it, such as to show a problem with it.
+	pub fn reveal(&mut self, uuid: Uuid) {
+		self.search_field.clear();
+		self.reveal = Some(uuid);
+	}
+
+	/// Draws each class as a box, with arrows to the classes it promotes into.
+	/// Clicking a class opens its entry.
+	fn promotion_graph(
+		&mut self,
+		ui: &Ui,
+		class_icons: &ClassIcons,
+		missing: &MissingTextures,
+		cycles: &[Uuid],
+		items: &[Item],
+	) {
+		if self.classes.is_empty() {
+			ui.text_disabled("There are no classes yet.");
+			return;
+		}
+
+		// Classes are placed in columns by how many promotions it takes to reach them,
+		// in the order they're listed.
+		let depths = promotion_depths(&self.classes);
+		let mut rows = vec![0; self.classes.len() + 1];
+		let mut positions = Vec::new();
+		for &depth in &depths {
+			positions.push([
+				depth as f32 * (GRAPH_NODE_SIZE[0] + GRAPH_SPACING[0]),
+				rows[depth] as f32 * (GRAPH_NODE_SIZE[1] + GRAPH_SPACING[1]),
+			]);
+			rows[depth] += 1;
+		}
+		let columns = depths.iter().max().map_or(1, |d| d + 1) as f32;
+		let rows = rows.into_iter().max().unwrap_or(1) as f32;
+		let size = [
+			columns * (GRAPH_NODE_SIZE[0] + GRAPH_SPACING[0]) - GRAPH_SPACING[0],
+			rows * (GRAPH_NODE_SIZE[1] + GRAPH_SPACING[1]) - GRAPH_SPACING[1],
+		];
+
+		ui.child_window("##graph")
+			.size([0.0, (size[1] + 24.0).min(300.0)])
+			.horizontal_scrollbar(true)
+			.build(|| {
+				let origin = ui.cursor_screen_pos();
+				let draw_list = ui.get_window_draw_list();
+				let at = |i: usize, x: f32, y: f32| {
+					[
+						origin[0] + positions[i][0] + x,
+						origin[1] + positions[i][1] + y,
+					]
+				};
+
+				// Promotions are drawn first so that the classes are drawn over them.
+				for (i, class) in self.classes.iter().enumerate() {
+					for promotion in &class.progression.promotions {
+						let target = self.classes.iter().position(|c| c.uuid == promotion.class);
+						let Some(target) = target else {
+							continue;
+						};
+						let from = at(i, GRAPH_NODE_SIZE[0], GRAPH_NODE_SIZE[1] / 2.0);
+						let to = at(target, 0.0, GRAPH_NODE_SIZE[1] / 2.0);
+						let in_cycle =
+							cycles.contains(&class.uuid) && cycles.contains(&promotion.class);
+						let color = if in_cycle {
+							CYCLE_COLOR
+						} else {
+							[0.8, 0.8, 0.8, 1.0]
+						};
+						draw_list.add_line(from, to, color).thickness(2.0).build();
+
+						// Point an arrowhead at the class being promoted into.
+						let (dx, dy) = (to[0] - from[0], to[1] - from[1]);
+						let length = (dx * dx + dy * dy).sqrt().max(1.0);
+						let (dx, dy) = (dx / length, dy / length);
+						let base = [to[0] - dx * 8.0, to[1] - dy * 8.0];
+						draw_list
+							.add_triangle(
+								to,
+								[base[0] - dy * 4.0, base[1] + dx * 4.0],
+								[base[0] + dy * 4.0, base[1] - dx * 4.0],
+								color,
+							)
+							.filled(true)
+							.build();
+
+						let mut label = format!("Lv {}", promotion.level);
+						if let Some(item) = promotion.item {
+							match items.iter().find(|i| i.uuid == item) {
+								Some(item) => label += &format!("\n{}", item.name),
+								None => label += "\nMissing item",
+							}
+						}
+						let label_size = ui.calc_text_size(&label);
+						draw_list.add_text(
+							[
+								(from[0] + to[0] - label_size[0]) / 2.0,
+								(from[1] + to[1]) / 2.0 - label_size[1] - 2.0,
+							],
+							color,
+							&label,
+						);
+					}
+				}
+
+				let mut reveal = None;
+				for (i, class) in self.classes.iter().enumerate() {
+					let _id = ui.push_id(class.uuid.to_string());
+					let p_min = at(i, 0.0, 0.0);
+					let p_max = at(i, GRAPH_NODE_SIZE[0], GRAPH_NODE_SIZE[1]);
+					ui.set_cursor_screen_pos(p_min);
+					if ui.invisible_button("##class", GRAPH_NODE_SIZE) {
+						reveal = Some(class.uuid);
+					}
+					let background = if ui.is_item_hovered() {
+						[0.3, 0.3, 0.4, 1.0]
+					} else {
+						[0.2, 0.2, 0.25, 1.0]
+					};
+					let border = if cycles.contains(&class.uuid) {
+						CYCLE_COLOR
+					} else {
+						[0.5, 0.5, 0.5, 1.0]
+					};
+					draw_list
+						.add_rect(p_min, p_max, background)
+						.filled(true)
+						.rounding(4.0)
+						.build();
+					draw_list
+						.add_rect(p_min, p_max, border)
+						.rounding(4.0)
+						.build();
+
+					let icon_size = GRAPH_NODE_SIZE[1] - 8.0;
+					draw_list
+						.add_image(
+							missing.icon(class_icons, &class.data.texture),
+							[p_min[0] + 4.0, p_min[1] + 4.0],
+							[p_min[0] + 4.0 + icon_size, p_min[1] + 4.0 + icon_size],
+						)
+						.build();
+					let name = if class.is_new() {
+						"New class"
+					} else {
+						&class.data.name
+					};
+					let name_height = ui.calc_text_size(name)[1];
+					draw_list.add_text(
+						[
+							p_min[0] + icon_size + 8.0,
+							p_min[1] + (GRAPH_NODE_SIZE[1] - name_height) / 2.0,
+						],
+						[1.0, 1.0, 1.0, 1.0],
+						name,
+					);
+				}
+
+				// Leave room for the graph so that the window can scroll over it.
+				ui.set_cursor_screen_pos(origin);
+				ui.dummy(size);
+
+				if let Some(uuid) = reveal {
+					self.reveal(uuid);
+				}
+			});
 	}
 
-	pub fn draw(&mut self, ui: &Ui, position: (f32, f32), class_icons: &ClassIcons) {
+	pub fn draw(
+		&mut self,
+		ui: &Ui,
+		position: (f32, f32),
+		class_icons: &ClassIcons,
+		missing: &MissingTextures,
+		movement_classes: &[String],
+		items: &[Item],
+	) {
 		// Track any changes that occur during this frame.
 		let mut editor_hash = DefaultHasher::new();
 		self.classes.hash(&mut editor_hash);
 		let editor_hash = editor_hash.finish();
 
-		ui.window("Classes")
+		self.focused = false;
+		let reveal = self.reveal.take();
+		let mut window = ui
+			.window("Classes")
 			.position([position.0, position.1], Condition::FirstUseEver)
 			.size([200.0, 400.0], Condition::FirstUseEver)
 			.menu_bar(true)
 			.focus_on_appearing(false)
 			.collapsed(true, Condition::FirstUseEver)
-			.unsaved_document(self.unsaved)
-			.build(|| {
-				ui.menu_bar(|| {
-					ui.menu_item("Save");
-				});
+			.unsaved_document(!self.save_state.is_saved());
+		if reveal.is_some() {
+			window = window.collapsed(false, Condition::Always).focused(true);
+		}
+		window.build(|| {
+			self.focused =
+				ui.is_window_focused_with_flags(WindowFocusedFlags::ROOT_AND_CHILD_WINDOWS);
 
-				ui.text("Search:");
-				ui.input_text("##search", &mut self.search_field).build();
+			ui.menu_bar(|| {
+				ui.menu_item("Save");
+			});
 
-				ui.separator();
+			let cycles = promotion_cycles(&self.classes);
+			if ui.collapsing_header("Promotion Graph", TreeNodeFlags::empty()) {
+				self.promotion_graph(ui, class_icons, missing, &cycles, items);
+			}
+			let references = ClassReferences {
+				classes: self
+					.classes
+					.iter()
+					.map(|c| (c.uuid, c.data.name.clone()))
+					.collect(),
+				cycles,
+				movement_classes,
+				items,
+			};
 
-				let normalized_query = if self.search_field.len() > 0 {
-					Some(self.search_field.to_ascii_lowercase())
-				} else {
-					None
-				};
+			ui.text("Search:");
+			ui.input_text("##search", &mut self.search_field).build();
 
-				for item in &mut self.classes {
-					if let Some(query) = &normalized_query {
-						if !item.data.name.to_ascii_lowercase().contains(query) {
-							continue;
-						}
+			ui.separator();
+
+			let normalized_query = if self.search_field.len() > 0 {
+				Some(self.search_field.to_ascii_lowercase())
+			} else {
+				None
+			};
+
+			for (index, item) in self.classes.iter_mut().enumerate() {
+				if let Some(query) = &normalized_query {
+					if !item.data.name.to_ascii_lowercase().contains(query) {
+						continue;
 					}
+				}
 
-					let _id = ui.push_id(&item.uuid.to_string());
+				let _id = ui.push_id(&item.uuid.to_string());
 
-					ui.tree_node_config("##header")
-						.label::<String, String>(if !item.is_new() {
-							item.data.name.clone()
+				let revealed = reveal == Some(item.uuid);
+				if revealed {
+					ui.set_scroll_here_y();
+				}
+
+				ui.tree_node_config("##header")
+					.label::<String, String>(if !item.is_new() {
+						item.data.name.clone()
+					} else {
+						format!("New class")
+					})
+					.framed(true)
+					// Open the item entry if the name is empty,
+					// since this means it's newly created; empty items can't be loaded from disk.
+					.opened(
+						item.is_new() || revealed,
+						if revealed {
+							Condition::Always
 						} else {
-							format!("New class")
-						})
-						.framed(true)
-						// Open the item entry if the name is empty,
-						// since this means it's newly created; empty items can't be loaded from disk.
-						.opened(item.is_new(), Condition::FirstUseEver)
-						.build(|| {
-							item.editor(&ui, &class_icons);
+							Condition::FirstUseEver
+						},
+					)
+					.build(|| {
+						if let Some(from) = item.editor(
+							&ui,
+							&class_icons,
+							missing,
+							&references,
+							&mut self.edit_start,
+						) {
+							self.history.push(ClassEdit::Modify {
+								index,
+								from: Box::new(from),
+								to: Box::new(ClassState::of(item)),
+							});
+						}
+						if ui.button("Delete") {
+							ui.open_popup("Delete");
+						}
+
+						if ui.modal_popup_config("Delete").build(|| {
+							ui.text(&format!(
+								"Do you really want to delete \"{}\"?",
+								item.data.name
+							));
+							if ui.button("Cancel") {
+								ui.close_current_popup();
+							}
+							ui.same_line();
 							if ui.button("Delete") {
-								ui.open_popup("Delete");
+								ui.close_current_popup();
+								return true;
 							}
+							false
+						}) == Some(true)
+						{
+							item.close()
+						}
+					});
 
-							if ui.modal_popup_config("Delete").build(|| {
-								ui.text(&format!(
-									"Do you really want to delete \"{}\"?",
-									item.data.name
-								));
-								if ui.button("Cancel") {
-									ui.close_current_popup();
-								}
-								ui.same_line();
-								if ui.button("Delete") {
-									ui.close_current_popup();
-									return true;
-								}
-								false
-							}) == Some(true)
-							{
-								item.close()
-							}
-						});
+				ui.separator();
+			}
 
-					ui.separator();
-				}
+			if ui.button(&format!("Create new class")) {
+				self.add_entry();
+			}
+		});
 
-				if ui.button(&format!("Create new class")) {
-					self.add_entry();
-				}
-			});
+		// Deleted classes are moved into the history so that they can be restored.
+		while let Some(index) = self.classes.iter().position(|i| !i.is_open) {
+			let entry = &self.classes[index];
+			let edit = ClassEdit::Remove(index, entry.uuid, ClassState::of(entry));
+			edit.apply(&mut self.classes);
+			self.history.push(edit);
+		}
 
 		let mut current_hash = DefaultHasher::new();
 		self.classes.hash(&mut current_hash);
 		let current_hash = current_hash.finish();
 
-		if !self.unsaved {
-			self.unsaved = editor_hash != current_hash;
+		if editor_hash != current_hash {
+			self.save_state.mark_unsaved();
 		}
 	}
 }
@@ -0,0 +1,255 @@
+//! Commands which run without opening a window, so that projects can be checked in CI.
+
+use crate::*;
+use std::error::Error;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+	/// Loads every file in the project and reports any problems.
+	Check,
+	/// Checks the project, then rewrites every file in the current format.
+	Convert,
+}
+
+impl Command {
+	pub fn from_arg(arg: &str) -> Option<Self> {
+		match arg {
+			"check" => Some(Self::Check),
+			"convert" => Some(Self::Convert),
+			_ => None,
+		}
+	}
+}
+
+struct Report {
+	errors: usize,
+	warnings: usize,
+}
+
+impl Report {
+	fn error(&mut self, file: impl Display, msg: impl Display) {
+		eprintln!("error: {file}: {msg}");
+		self.errors += 1;
+	}
+
+	fn warning(&mut self, file: impl Display, msg: impl Display) {
+		eprintln!("warning: {file}: {msg}");
+		self.warnings += 1;
+	}
+
+	fn diagnostic(&mut self, diagnostic: &Diagnostic) {
+		match diagnostic.severity {
+			Severity::Error => {
+				eprintln!("error: {diagnostic}");
+				self.errors += 1;
+			}
+			Severity::Warning => {
+				eprintln!("warning: {diagnostic}");
+				self.warnings += 1;
+			}
+		}
+	}
+
+	/// Prints a summary, and returns false if there were any errors.
+	fn finish(self) -> bool {
+		eprintln!("{} error(s), {} warning(s)", self.errors, self.warnings);
+		self.errors == 0
+	}
+}
+
+/// Runs a command on the project at `path`, printing any problems to stderr.
+/// Returns false if the project has errors.
+pub fn run(command: Command, path: &Path, layout: &TilesetLayout) -> bool {
+	let mut report = Report {
+		errors: 0,
+		warnings: 0,
+	};
+
+	let tile_count = match image::open(path.join("tileset.png")) {
+		Ok(image) => match tile_count(&image, layout) {
+			Ok(count) => Some(count),
+			Err(msg) => {
+				report.error("tileset.png", msg);
+				None
+			}
+		},
+		Err(msg) => {
+			report.error("tileset.png", msg);
+			None
+		}
+	};
+
+	let mut icons = Vec::new();
+	match fs::read_dir(path.join("class-icons")) {
+		Ok(dir) => icons.extend(dir.filter_map(|e| e.ok()).map(|e| e.path())),
+		Err(msg) => report.error("class-icons", msg),
+	}
+	if icons.is_empty() {
+		report.error("class-icons", "No unit icons found");
+	}
+
+	let default_icon = icons.first().cloned().unwrap_or_default();
+	let class_editor = match ClassEditor::open(path.join("classes.toml"), default_icon) {
+		Ok(class_editor) => class_editor,
+		Err(msg) => {
+			// Maps can't be checked without their classes.
+			report.error("classes.toml", msg);
+			return report.finish();
+		}
+	};
+	// Unlike class icons, item icons are optional.
+	let mut item_icons = Vec::new();
+	if let Ok(dir) = fs::read_dir(path.join("item-icons")) {
+		item_icons.extend(dir.filter_map(|e| e.ok()).map(|e| e.path()));
+	}
+	let item_editor = match ItemEditor::open(path.join("items.toml")) {
+		Ok(item_editor) => item_editor,
+		Err(msg) => {
+			// Maps can't be checked without their items.
+			report.error("items.toml", msg);
+			return report.finish();
+		}
+	};
+	for problem in lint_items(&item_editor.items, |path| {
+		item_icons.iter().any(|icon| icon == path)
+	}) {
+		report.diagnostic(&problem);
+	}
+
+	let mut portraits = Vec::new();
+	if let Ok(dir) = fs::read_dir(path.join("portraits")) {
+		portraits.extend(dir.filter_map(|e| e.ok()).map(|e| e.path()));
+	}
+	let character_editor = match CharacterEditor::open(path.join("characters.toml")) {
+		Ok(character_editor) => character_editor,
+		Err(msg) => {
+			// Maps can't be checked without their characters.
+			report.error("characters.toml", msg);
+			return report.finish();
+		}
+	};
+	for problem in lint_characters(
+		&character_editor.characters,
+		&class_editor.classes,
+		|path| portraits.iter().any(|portrait| portrait == path),
+	) {
+		report.diagnostic(&problem);
+	}
+
+	let terrain_editor =
+		match TerrainEditor::open(path.join("terrain.toml"), tile_count.unwrap_or(0)) {
+			Ok(terrain_editor) => Some(terrain_editor),
+			Err(msg) => {
+				report.error("terrain.toml", msg);
+				None
+			}
+		};
+
+	// Classes refer to items and movement classes, so they're checked once both are loaded.
+	// If terrain.toml couldn't be read, its error is enough; movement classes aren't checked.
+	let movement_classes = match &terrain_editor {
+		Some(terrain_editor) => terrain_editor.data.movement_classes.clone(),
+		None => class_editor
+			.classes
+			.iter()
+			.map(|c| c.progression.movement.clone())
+			.collect(),
+	};
+	for problem in lint_classes(
+		&class_editor.classes,
+		&item_editor.items,
+		&movement_classes,
+		|path| icons.iter().any(|icon| icon == path),
+	) {
+		report.diagnostic(&problem);
+	}
+
+	let factions = match Factions::open(path.join("factions.toml")) {
+		Ok(factions) => factions,
+		Err(msg) => {
+			report.error("factions.toml", msg);
+			Factions::new()
+		}
+	};
+
+	let maps_path = path.join("maps");
+	let mut maps = Vec::new();
+	match fs::read_dir(&maps_path) {
+		Ok(dir) => {
+			for entry in dir.filter_map(|e| e.ok()) {
+				// Only TOML files are maps; anything else, such as a backup, is left alone.
+				let file_name = entry.file_name().to_string_lossy().to_string();
+				let Some(name) = file_name.strip_suffix(".toml") else {
+					continue;
+				};
+				let name = name.to_string();
+				let file = format!("maps/{name}.toml");
+				match MapEditor::open(&maps_path, name, &class_editor.classes, &factions) {
+					Ok(map_editor) => {
+						// Without a tileset, every tile would be reported as missing.
+						let tile_count = tile_count.unwrap_or(usize::MAX);
+						for problem in lint_map(
+							&map_editor,
+							&class_editor.classes,
+							&item_editor.items,
+							&character_editor.characters,
+							&factions,
+							tile_count,
+						) {
+							report.diagnostic(&problem);
+						}
+						maps.push(map_editor);
+					}
+					Err(msg) => report.error(file, msg),
+				}
+			}
+		}
+		Err(msg) => report.warning("maps", msg),
+	}
+
+	if command == Command::Convert {
+		match terrain_editor {
+			Some(terrain_editor) if report.errors == 0 => {
+				if let Err(msg) = convert(
+					path,
+					&class_editor,
+					&terrain_editor,
+					&item_editor,
+					&character_editor,
+					&maps,
+				) {
+					report.error(path.display(), msg);
+				}
+			}
+			_ => eprintln!("Not converting a project with errors."),
+		}
+	}
+
+	report.finish()
+}
+
+/// Rewrites every file in the project, such as to migrate maps which refer to classes by index.
+fn convert(
+	path: &Path,
+	class_editor: &ClassEditor,
+	terrain_editor: &TerrainEditor,
+	item_editor: &ItemEditor,
+	character_editor: &CharacterEditor,
+	maps: &[MapEditor],
+) -> Result<(), Box<dyn Error>> {
+	fs::write(path.join("classes.toml"), class_editor.to_toml()?)?;
+	fs::write(path.join("terrain.toml"), terrain_editor.to_toml()?)?;
+	fs::write(path.join("items.toml"), item_editor.to_toml()?)?;
+	fs::write(path.join("characters.toml"), character_editor.to_toml()?)?;
+	for map_editor in maps {
+		let file = path
+			.join("maps")
+			.join(format!("{}.toml", map_editor.data.name));
+		fs::write(file, map_editor.to_toml()?)?;
+	}
+	eprintln!("Converted {} map(s).", maps.len());
+	Ok(())
+}
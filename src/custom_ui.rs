@@ -1,10 +1,22 @@
 use crate::*;
-use imgui::*;
 use imgui::color::ImColor32;
+use imgui::*;
 
 const MOUSE_WHEEL_ZOOM_SPEED: f32 = 3.0;
 const KEYBOARD_ZOOM_SPEED: f32 = 32.0;
 const KEYBOARD_DRAG_SPEED: f32 = 1024.0;
+/// Marks units whose class has been deleted.
+const MISSING_CLASS_COLOR: [f32; 4] = [0.8, 0.2, 0.2, 1.0];
+
+/// What the tilemap drew in a single frame, for the debug overlay.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DrawStats {
+	pub tiles: usize,
+	pub units: usize,
+	pub spawns: usize,
+	/// Images, rectangles and text drawn for the tiles, units, and spawns.
+	pub commands: usize,
+}
 
 pub struct ModalCapsule {
 	should_open: bool,
@@ -32,23 +44,131 @@ impl ModalCapsule {
 	}
 }
 
+/// Highlights a rectangle of tiles on the map.
+fn draw_tile_rect(draw_list: &DrawListMut, map: &MapEditor, rect: TileRect, window_pos: [f32; 2]) {
+	let x = window_pos[0] + map.scroll[0] + (rect.x as f32) * map.zoom;
+	let y = window_pos[1] + map.scroll[1] + (rect.y as f32) * map.zoom;
+	let width = (rect.width as f32) * map.zoom;
+	let height = (rect.height as f32) * map.zoom;
+	draw_list
+		.add_rect(
+			[x, y],
+			[x + width, y + height],
+			ImColor32::from_rgba(255, 255, 255, 48),
+		)
+		.filled(true)
+		.build();
+	draw_list
+		.add_rect([x, y], [x + width, y + height], ImColor32::WHITE)
+		.thickness(2.0)
+		.build();
+}
+
+/// Returns the tiles which are at least partly inside a window of `window_size`.
+fn visible_tiles(map: &MapEditor, window_size: [f32; 2]) -> TileRect {
+	let first =
+		|scroll: f32, length: usize| ((-scroll / map.zoom).floor().max(0.0) as usize).min(length);
+	let last = |scroll: f32, size: f32, length: usize| {
+		(((size - scroll) / map.zoom).ceil().max(0.0) as usize).min(length)
+	};
+	let x = first(map.scroll[0], map.data.width);
+	let y = first(map.scroll[1], map.data.height);
+	TileRect {
+		x,
+		y,
+		width: last(map.scroll[0], window_size[0], map.data.width).saturating_sub(x),
+		height: last(map.scroll[1], window_size[1], map.data.height).saturating_sub(y),
+	}
+}
+
+/// Draws every visible layer which is either above or below units.
+/// Only tiles within `visible` are drawn, so that large maps stay fast.
+/// Returns the number of tiles drawn.
+fn draw_layers(
+	draw_list: &DrawListMut,
+	map: &MapEditor,
+	texture_atlas: &TileAtlas,
+	missing: &MissingTextures,
+	above_units: bool,
+	window_pos: [f32; 2],
+	visible: TileRect,
+) -> usize {
+	let mut drawn = 0;
+	for (i, layer) in map.layers.iter().enumerate() {
+		if !layer.visible || layer.above_units != above_units {
+			continue;
+		}
+		let color = ImColor32::from_rgba_f32s(1.0, 1.0, 1.0, layer.opacity);
+		let tiles = map.tiles(i);
+		for ty in visible.y..visible.y + visible.height {
+			for tx in visible.x..visible.x + visible.width {
+				let tile = tiles[tx + ty * map.data.width];
+				if tile == EMPTY_TILE {
+					continue;
+				}
+				let x = (tx as f32) * map.zoom + map.scroll[0] + window_pos[0];
+				let y = (ty as f32) * map.zoom + map.scroll[1] + window_pos[1];
+				missing
+					.tile(texture_atlas, tile)
+					.draw(draw_list, [x, y], [x + map.zoom, y + map.zoom])
+					.col(color)
+					.build();
+				drawn += 1;
+			}
+		}
+	}
+	drawn
+}
+
+/// Remembers an entry's state when the last item (such as a text field) is activated,
+/// and returns it once the item is done being edited.
+pub(crate) fn track_edit<T: Clone>(ui: &Ui, before: &T, edit_start: &mut Option<T>) -> Option<T> {
+	if ui.is_item_activated() {
+		*edit_start = Some(before.clone());
+	}
+	if ui.is_item_deactivated_after_edit() {
+		edit_start.take()
+	} else {
+		None
+	}
+}
+
+/// The parts of the project which are looked up while drawing a map.
+#[derive(Clone, Copy)]
+pub struct ProjectContext<'a> {
+	pub classes: &'a [ClassEntry],
+	pub characters: &'a [Character],
+	pub class_icons: &'a ClassIcons,
+	pub factions: &'a Factions,
+	pub missing: &'a MissingTextures,
+}
+
 pub trait CustomUi {
 	fn hover_tooltip(&self, message: &str);
 	fn tilemap(
 		&self,
 		map: &mut MapEditor,
-		texture_atlas: &Vec<TextureId>,
-		classes: &Vec<ClassEntry>,
-		class_icons: &ClassIcons,
+		texture_atlas: &TileAtlas,
+		project: ProjectContext,
 		cursor_tile: TextureId,
 		selected_tile: usize,
-	);
+		tool: MapTool,
+	) -> DrawStats;
 	fn tile_selector(
 		&self,
-		texture_atlas: &Vec<TextureId>,
+		texture_atlas: &TileAtlas,
 		selected_tile: usize,
 		highlight_tile: TextureId,
+		allow_empty: bool,
 	) -> usize;
+	fn layer_list(&self, map: &mut MapEditor);
+	fn unit_inspector(
+		&self,
+		map: &mut MapEditor,
+		classes: &[ClassEntry],
+		characters: &[Character],
+		items: &[Item],
+	);
 }
 
 impl CustomUi for Ui {
@@ -63,27 +183,51 @@ impl CustomUi for Ui {
 	fn tilemap(
 		&self,
 		map: &mut MapEditor,
-		texture_atlas: &Vec<TextureId>,
-		classes: &Vec<ClassEntry>,
-		class_icons: &ClassIcons,
+		texture_atlas: &TileAtlas,
+		project: ProjectContext,
 		cursor_tile: TextureId,
 		selected_tile: usize,
-	) {
+		tool: MapTool,
+	) -> DrawStats {
+		let ProjectContext {
+			classes,
+			characters,
+			class_icons,
+			factions,
+			missing,
+		} = project;
 		let window_pos = self.window_pos();
+		let window_size = self.window_size();
 		let draw_list = self.get_window_draw_list();
 		let delta = self.io().delta_time;
+		let visible = visible_tiles(map, window_size);
+		let mut stats = DrawStats::default();
 
-		for ty in 0..map.data.height {
-			for tx in 0..map.data.width {
-				let tile = *map.get_tile(tx, ty);
-				let x = (tx as f32) * map.zoom + map.scroll[0] + window_pos[0];
-				let y = (ty as f32) * map.zoom + map.scroll[1] + window_pos[1];
-				draw_list
-					.add_image(texture_atlas[tile], [x, y], [x + map.zoom, y + map.zoom])
-					.build();
+		stats.tiles += draw_layers(
+			&draw_list,
+			map,
+			texture_atlas,
+			missing,
+			false,
+			window_pos,
+			visible,
+		);
+
+		// Strokes and drags end once the mouse is released, even if it's no longer over the map.
+		if !self.is_key_down(Key::MouseLeft) {
+			map.end_stroke();
+			if let Some(drag_start) = map.drag_start.take() {
+				let rect = TileRect::from_corners(drag_start, map.drag_end);
+				match tool {
+					MapTool::Brush | MapTool::Bucket => {}
+					MapTool::Rectangle => map.fill_rect(rect, selected_tile),
+					MapTool::Select => map.selection = Some(rect),
+				}
 			}
 		}
 
+		let mut hovered_tile = None;
+
 		// Only handle input if the window is hovered.
 		if self.is_window_hovered() {
 			let x = (self.io().mouse_pos[0] - map.scroll[0] - window_pos[0]) / map.zoom;
@@ -127,8 +271,51 @@ impl CustomUi for Ui {
 
 			// Only if the cursor is over the map.
 			if x >= 0.0 && y >= 0.0 && x < (map.data.width as f32) && y < (map.data.height as f32) {
-				if self.is_key_down(Key::MouseLeft) {
-					*map.get_tile(x.floor() as usize, y.floor() as usize) = selected_tile;
+				let tile_position = (x.floor() as usize, y.floor() as usize);
+				hovered_tile = Some(tile_position);
+				if map.paste.is_some() {
+					// Wait for the mouse to be released so that the click isn't also used by a tool.
+					if self.is_mouse_released(MouseButton::Left) {
+						if let Some(region) = map.paste.take() {
+							map.selection =
+								Some(map.paste_region(&region, tile_position.0, tile_position.1));
+						}
+					}
+				} else {
+					match tool {
+						MapTool::Brush => {
+							if self.is_key_down(Key::MouseLeft) {
+								map.paint(tile_position.0, tile_position.1, selected_tile);
+							}
+						}
+						MapTool::Bucket => {
+							if self.is_mouse_clicked(MouseButton::Left) {
+								if self.io().key_shift {
+									let target = *map.get_tile(
+										map.active_layer,
+										tile_position.0,
+										tile_position.1,
+									);
+									map.replace_all(target, selected_tile);
+								} else {
+									map.flood_fill(tile_position.0, tile_position.1, selected_tile);
+								}
+							}
+						}
+						MapTool::Rectangle | MapTool::Select => {
+							if self.is_mouse_clicked(MouseButton::Left) {
+								map.drag_start = Some(tile_position);
+								// Selecting a unit shows it in the unit inspector.
+								let position = (tile_position.0 as u32, tile_position.1 as u32);
+								if tool == MapTool::Select
+									&& map.units.iter().any(|u| (u.x, u.y) == position)
+								{
+									map.inspected = Some(position);
+								}
+							}
+							map.drag_end = tile_position;
+						}
+					}
 				}
 
 				if self.is_key_down(Key::MouseRight) {
@@ -136,17 +323,18 @@ impl CustomUi for Ui {
 					map.info_popup.position = (x.floor() as u32, y.floor() as u32);
 				}
 
-				if !self.is_key_down(Key::MouseMiddle) {
+				if !self.is_key_down(Key::MouseMiddle)
+					&& map.drag_start.is_none()
+					&& map.paste.is_none()
+				{
 					let tx = x.floor() * map.zoom + map.scroll[0] + window_pos[0];
 					let ty = y.floor() * map.zoom + map.scroll[1] + window_pos[1];
 					// Draw a placement preview.
-					draw_list
-						.add_image(
-							texture_atlas[selected_tile],
-							[tx, ty],
-							[tx + map.zoom, ty + map.zoom],
-						)
-						.build();
+					if tool != MapTool::Select && selected_tile != EMPTY_TILE {
+						texture_atlas[selected_tile]
+							.draw(&draw_list, [tx, ty], [tx + map.zoom, ty + map.zoom])
+							.build();
+					}
 					draw_list
 						.add_image(cursor_tile, [tx, ty], [tx + map.zoom, ty + map.zoom])
 						.build();
@@ -154,6 +342,23 @@ impl CustomUi for Ui {
 			}
 		}
 
+		// Preview the rectangle being dragged out.
+		if let Some(drag_start) = map.drag_start {
+			let rect = TileRect::from_corners(drag_start, map.drag_end);
+			if tool == MapTool::Rectangle && selected_tile != EMPTY_TILE {
+				for ty in rect.y..rect.y + rect.height {
+					for tx in rect.x..rect.x + rect.width {
+						let x = (tx as f32) * map.zoom + map.scroll[0] + window_pos[0];
+						let y = (ty as f32) * map.zoom + map.scroll[1] + window_pos[1];
+						texture_atlas[selected_tile]
+							.draw(&draw_list, [x, y], [x + map.zoom, y + map.zoom])
+							.build();
+					}
+				}
+			}
+			draw_tile_rect(&draw_list, map, rect, window_pos);
+		}
+
 		self.popup("info", || {
 			self.text("Tile Attributes");
 			if self.button("Close") {
@@ -161,22 +366,9 @@ impl CustomUi for Ui {
 			}
 			self.separator();
 
-			let mut unit = None;
-			// This index is needed to remove the unit upon deletion.
-			let mut unit_index = 0;
-			for (i, u) in map.data.units.iter_mut().enumerate() {
-				if (u.x, u.y) == map.info_popup.position {
-					unit = Some(u);
-					unit_index = i;
-				}
-			}
-
-			let mut spawnpoint_index = None;
-			for (i, u) in map.data.spawns.iter_mut().enumerate() {
-				if *u == map.info_popup.position {
-					spawnpoint_index = Some(i);
-				}
-			}
+			let position = map.info_popup.position;
+			let unit_index = map.units.iter().position(|u| (u.x, u.y) == position);
+			let spawnpoint_index = map.data.spawns.iter().position(|s| *s == position);
 
 			self.popup("class menu", || {
 				for (i, class) in classes.iter().enumerate() {
@@ -184,107 +376,312 @@ impl CustomUi for Ui {
 					if i % 3 != 0 {
 						self.same_line();
 					}
-					if self.image_button(i.to_string(), class_icons[&class.data.texture], [32.0; 2]) {
-						let unit = unit
-							.as_mut()
-							.expect("No unit found but class popup is open");
-						unit.class = i
+					if self.image_button(
+						i.to_string(),
+						missing.icon(class_icons, &class.data.texture),
+						[32.0; 2],
+					) {
+						let index = unit_index.expect("No unit found but class popup is open");
+						let from = map.units[index].clone();
+						let to = Unit {
+							class: class.uuid,
+							..from.clone()
+						};
+						map.apply(MapEdit::ModifyUnit { index, from, to });
 					}
 					self.hover_tooltip(&class.data.name);
 				}
 			});
 
-			if let Some(unit) = unit {
+			if let Some(unit_index) = unit_index {
 				// Unit selected
-				self.text(&format!("Class:\n{}", classes[unit.class].data.name));
-				self.same_line();
-				if self.image_button(
-					"Class selector",
-					class_icons[&classes[unit.class].data.texture],
-					[32.0; 2],
-				) {
-					self.open_popup("class menu");
+				let class_id = map.units[unit_index].class;
+				if let Some(class) = classes.iter().find(|c| c.uuid == class_id) {
+					self.text(&format!("Class:\n{}", class.data.name));
+					self.same_line();
+					if self.image_button(
+						"Class selector",
+						missing.icon(class_icons, &class.data.texture),
+						[32.0; 2],
+					) {
+						self.open_popup("class menu");
+					}
+				} else {
+					// The class was deleted after the unit was placed.
+					self.text_colored(MISSING_CLASS_COLOR, "Class:\nMissing");
+					self.same_line();
+					if self.button_with_size("?##Class selector", [32.0; 2]) {
+						self.open_popup("class menu");
+					}
 				}
 				self.hover_tooltip("Click to select class");
-				self.input_text("##name", &mut unit.name)
-					.hint("Name (Optional)")
-					.build();
+
+				let unit = &mut map.units[unit_index];
+				let name = unit.name.clone();
+				if unit.character.is_some() {
+					// Characters are named in the character editor.
+					self.text(format!("Character:\n{}", unit.display_name(characters)));
+				} else {
+					self.input_text("##name", &mut unit.name)
+						.hint("Name (Optional)")
+						.build();
+				}
+				// Record the name as one edit once the user is done typing.
+				if self.is_item_activated() {
+					map.info_popup.unit = name;
+				}
+				if self.is_item_deactivated_after_edit() {
+					let to = map.units[unit_index].clone();
+					let from = Unit {
+						name: std::mem::take(&mut map.info_popup.unit),
+						..to.clone()
+					};
+					map.record(MapEdit::ModifyUnit {
+						index: unit_index,
+						from,
+						to,
+					});
+				}
+
+				let unit = &map.units[unit_index];
+				let mut names: Vec<String> =
+					factions.factions.iter().map(|f| f.name.clone()).collect();
+				// Keep factions which aren't in the project selected, rather than silently changing them.
+				let mut selected = match names.iter().position(|n| *n == unit.faction) {
+					Some(i) => i,
+					None => {
+						names.push(unit.faction.clone());
+						names.len() - 1
+					}
+				};
+				if self.combo_simple_string("Faction", &mut selected, &names)
+					&& selected < factions.factions.len()
+				{
+					let from = unit.clone();
+					let to = Unit {
+						faction: names[selected].clone(),
+						..from.clone()
+					};
+					map.apply(MapEdit::ModifyUnit {
+						index: unit_index,
+						from,
+						to,
+					});
+				}
+
+				if self.button("Inspect") {
+					map.inspected = Some(position);
+					self.close_current_popup();
+				}
+				self.same_line();
 				if self.button("Delete Unit") {
-					map.data.units.remove(unit_index);
+					let unit = map.units[unit_index].clone();
+					map.apply(MapEdit::RemoveUnit(unit_index, unit));
 					self.close_current_popup();
 				}
 			} else if let Some(spawnpoint_index) = spawnpoint_index {
 				// Spawnpoint selected
 				if self.button("Delete Spawn") {
-					map.data.spawns.remove(spawnpoint_index);
+					map.apply(MapEdit::RemoveSpawn(spawnpoint_index, position));
 					self.close_current_popup();
 				}
 			} else {
 				// Nothing selected
 				if self.button("Place Unit") && classes.len() > 0 {
-					let unit =
-						MapUnit::at_position(map.info_popup.position.0, map.info_popup.position.1);
-					map.data.units.push(unit);
+					let unit = Unit::new(
+						position.0,
+						position.1,
+						classes[0].uuid,
+						factions.default_faction(),
+					);
+					map.apply(MapEdit::InsertUnit(map.units.len(), unit));
 				};
 				if classes.len() == 0 {
 					self.hover_tooltip("Cannot create unit: No classes are defined.");
 				}
 				if self.button("Mark as spawn") {
-					map.data.spawns.push(map.info_popup.position);
+					map.apply(MapEdit::InsertSpawn(map.data.spawns.len(), position));
 					self.close_current_popup();
 				}
 			}
 		});
 
-		for i in &map.data.units {
+		// Units and spawns are culled by position rather than by `visible`,
+		// so that ones which are outside of the map are still shown.
+		let on_screen = |x: f32, y: f32| {
+			x + map.zoom > window_pos[0]
+				&& y + map.zoom > window_pos[1]
+				&& x < window_pos[0] + window_size[0]
+				&& y < window_pos[1] + window_size[1]
+		};
+
+		for i in &map.units {
 			let x = window_pos[0] + map.scroll[0] + (i.x as f32) * map.zoom;
 			let y = window_pos[1] + map.scroll[1] + (i.y as f32) * map.zoom;
-			draw_list
-				.add_image(
-					class_icons[&classes[i.class].data.texture],
-					[x, y],
-					[x + map.zoom, y + map.zoom],
-				)
-				.build();
-			if i.name.len() > 0 {
+			if !on_screen(x, y) {
+				continue;
+			}
+			stats.units += 1;
+			// Tint the unit's tile with its faction's color.
+			let faction = factions.get(&i.faction);
+			if let Some(faction) = faction {
+				draw_list
+					.add_rect(
+						[x, y],
+						[x + map.zoom, y + map.zoom],
+						faction.color_with_alpha(0.35),
+					)
+					.filled(true)
+					.build();
+				stats.commands += 1;
+			}
+			if let Some(class) = classes.iter().find(|c| c.uuid == i.class) {
+				draw_list
+					.add_image(
+						missing.icon(class_icons, &class.data.texture),
+						[x, y],
+						[x + map.zoom, y + map.zoom],
+					)
+					.build();
+				stats.commands += 1;
+			} else {
+				draw_list
+					.add_rect([x, y], [x + map.zoom, y + map.zoom], MISSING_CLASS_COLOR)
+					.filled(true)
+					.build();
+				draw_list.add_text([x + 4.0, y + 4.0], ImColor32::WHITE, "?");
+				stats.commands += 2;
+			}
+			if let Some(faction) = faction {
+				draw_list
+					.add_rect(
+						[x, y],
+						[x + map.zoom, y + map.zoom],
+						faction.color_with_alpha(1.0),
+					)
+					.thickness(2.0)
+					.build();
+				stats.commands += 1;
+			}
+			let name = i.display_name(characters);
+			if !name.is_empty() {
 				const NAME_MARGIN: f32 = 4.0;
-				let size = self.calc_text_size(&i.name);
+				let size = self.calc_text_size(name);
 				let center = x + map.zoom / 2.0 - size[0] / 2.0;
 				draw_list
 					.add_rect(
 						[center - NAME_MARGIN, y - size[1] - NAME_MARGIN],
 						[center + size[0] + NAME_MARGIN, y + NAME_MARGIN],
-						ImColor32::from_rgb(20, 20, 20)
+						ImColor32::from_rgb(20, 20, 20),
 					)
 					.filled(true)
 					.rounding(5.0)
 					.build();
-				draw_list.add_text([center, y - size[1]], ImColor32::WHITE, &i.name);
+				draw_list.add_text([center, y - size[1]], ImColor32::WHITE, name);
+				stats.commands += 2;
 			}
 		}
 
 		for i in &map.data.spawns {
 			let x = window_pos[0] + map.scroll[0] + (i.0 as f32) * map.zoom;
 			let y = window_pos[1] + map.scroll[1] + (i.1 as f32) * map.zoom;
+			if !on_screen(x, y) {
+				continue;
+			}
 			draw_list
 				.add_image(cursor_tile, [x, y], [x + map.zoom, y + map.zoom])
 				.build();
+			stats.spawns += 1;
+		}
+
+		stats.tiles += draw_layers(
+			&draw_list,
+			map,
+			texture_atlas,
+			missing,
+			true,
+			window_pos,
+			visible,
+		);
+		stats.commands += stats.tiles + stats.spawns;
+
+		if let (Some(selection), None) = (map.selection, map.drag_start) {
+			draw_tile_rect(&draw_list, map, selection, window_pos);
 		}
+
+		// Preview the region being pasted under the cursor.
+		if let (Some(region), Some((px, py))) = (&map.paste, hovered_tile) {
+			let tile_x = |x: usize| window_pos[0] + map.scroll[0] + ((px + x) as f32) * map.zoom;
+			let tile_y = |y: usize| window_pos[1] + map.scroll[1] + ((py + y) as f32) * map.zoom;
+			for tiles in &region.layers {
+				for ry in 0..region.height {
+					for rx in 0..region.width {
+						let tile = tiles[rx + ry * region.width];
+						if tile == EMPTY_TILE {
+							continue;
+						}
+						let (x, y) = (tile_x(rx), tile_y(ry));
+						missing
+							.tile(texture_atlas, tile)
+							.draw(&draw_list, [x, y], [x + map.zoom, y + map.zoom])
+							.build();
+					}
+				}
+			}
+			for unit in &region.units {
+				if let Some(class) = classes.iter().find(|c| c.uuid == unit.class) {
+					let (x, y) = (tile_x(unit.x as usize), tile_y(unit.y as usize));
+					draw_list
+						.add_image(
+							missing.icon(class_icons, &class.data.texture),
+							[x, y],
+							[x + map.zoom, y + map.zoom],
+						)
+						.build();
+				}
+			}
+			for spawn in &region.spawns {
+				let (x, y) = (tile_x(spawn.0 as usize), tile_y(spawn.1 as usize));
+				draw_list
+					.add_image(cursor_tile, [x, y], [x + map.zoom, y + map.zoom])
+					.build();
+			}
+			let rect = TileRect {
+				x: px,
+				y: py,
+				width: region.width,
+				height: region.height,
+			};
+			draw_tile_rect(&draw_list, map, rect, window_pos);
+		}
+
+		stats
 	}
 
 	fn tile_selector(
 		&self,
-		texture_atlas: &Vec<TextureId>,
+		texture_atlas: &TileAtlas,
 		mut selected_tile: usize,
 		highlight_tile: TextureId,
+		allow_empty: bool,
 	) -> usize {
+		if allow_empty {
+			if self.button_with_size("Erase", [64.0, 64.0]) {
+				selected_tile = EMPTY_TILE;
+			}
+			if selected_tile == EMPTY_TILE {
+				self.get_window_draw_list()
+					.add_image(highlight_tile, self.item_rect_min(), self.item_rect_max())
+					.build();
+			}
+		}
 		for (i, texture) in texture_atlas.iter().enumerate() {
 			if self.invisible_button(i.to_string(), [64.0, 64.0]) {
 				selected_tile = i;
 			}
 			let draw_list = self.get_window_draw_list();
-			draw_list
-				.add_image(*texture, self.item_rect_min(), self.item_rect_max())
+			texture
+				.draw(&draw_list, self.item_rect_min(), self.item_rect_max())
 				.build();
 			if selected_tile == i {
 				draw_list
@@ -295,4 +692,196 @@ impl CustomUi for Ui {
 
 		selected_tile
 	}
+
+	fn layer_list(&self, map: &mut MapEditor) {
+		let mut remove = None;
+		// List from the top down, in the order the layers appear on the map.
+		for i in (0..map.layers.len()).rev() {
+			let _id = self.push_id_usize(i);
+			let layer = &mut map.layers[i];
+			let before = layer.settings();
+			let mut edit = None;
+			if self.checkbox("##visible", &mut layer.visible) {
+				edit = Some(before.clone());
+			}
+			self.hover_tooltip("Visible");
+			self.same_line();
+			if self.checkbox("##locked", &mut layer.locked) {
+				edit = Some(before.clone());
+			}
+			self.hover_tooltip("Locked");
+			self.same_line();
+			if self
+				.selectable_config(&layer.name)
+				.selected(map.active_layer == i)
+				.build()
+			{
+				map.active_layer = i;
+			}
+			if map.active_layer == i {
+				self.indent();
+				self.input_text("Name", &mut layer.name).build();
+				edit = edit.or(track_edit(self, &before, &mut map.layer_edit));
+				self.slider("Opacity", 0.0, 1.0, &mut layer.opacity);
+				edit = edit.or(track_edit(self, &before, &mut map.layer_edit));
+				if self.checkbox("Draw above units", &mut layer.above_units) {
+					edit = Some(before.clone());
+				}
+				if i != 0 && self.button("Delete Layer") {
+					remove = Some(i);
+				}
+				self.unindent();
+			}
+			if let Some(from) = edit {
+				let to = map.layers[i].settings();
+				map.record(MapEdit::ModifyLayer { index: i, from, to });
+			}
+		}
+
+		if self.button("Add Layer") {
+			map.add_layer();
+		}
+		if let Some(layer) = remove {
+			map.remove_layer(layer);
+		}
+	}
+
+	fn unit_inspector(
+		&self,
+		map: &mut MapEditor,
+		classes: &[ClassEntry],
+		characters: &[Character],
+		items: &[Item],
+	) {
+		let Some(index) = map.inspected_unit() else {
+			self.text_disabled("Select a unit to inspect it.");
+			return;
+		};
+		let before = map.units[index].clone();
+		let class = classes.iter().find(|c| c.uuid == before.class);
+		let unit = &mut map.units[index];
+		let mut edit = None;
+
+		match class {
+			Some(class) => self.text(&class.data.name),
+			None => self.text_colored(MISSING_CLASS_COLOR, "Missing class"),
+		}
+		self.same_line();
+		self.text_disabled(format!("at ({}, {})", unit.x, unit.y));
+
+		let mut names = vec!["None"];
+		names.extend(characters.iter().map(|c| c.name.as_str()));
+		let mut selected = match unit.character {
+			Some(uuid) => match characters.iter().position(|c| c.uuid == uuid) {
+				Some(i) => i + 1,
+				None => {
+					names.push("Missing character");
+					names.len() - 1
+				}
+			},
+			None => 0,
+		};
+		if self.combo_simple_string("Character", &mut selected, &names)
+			&& selected <= characters.len()
+		{
+			let character = selected.checked_sub(1).map(|i| &characters[i]);
+			unit.character = character.map(|c| c.uuid);
+			// Characters bring their default class with them.
+			if let Some(class) = character.and_then(|c| c.class) {
+				unit.class = class;
+			}
+			edit = Some(before.clone());
+		}
+		if unit.character.is_none() {
+			self.input_text("Name", &mut unit.name)
+				.hint("Optional")
+				.build();
+			edit = edit.or(track_edit(self, &before, &mut map.inspector_edit));
+		}
+		self.input_scalar("Level", &mut unit.level).step(1).build();
+		unit.level = unit.level.clamp(1, MAX_LEVEL);
+		edit = edit.or(track_edit(self, &before, &mut map.inspector_edit));
+
+		// Stats which aren't overridden show the character's or class's base, greyed out.
+		self.separator();
+		let bases = unit
+			.base_stats(classes, characters)
+			.unwrap_or(Stats([0; STAT_COUNT]));
+		self.columns(3, "##stats", false);
+		for header in ["Stat", "Override", "Value"] {
+			self.text(header);
+			self.next_column();
+		}
+		for (i, name) in STAT_NAMES.iter().enumerate() {
+			let _id = self.push_id_usize(i);
+			self.text(name);
+			self.next_column();
+			let mut overridden = unit.stats.0[i].is_some();
+			if self.checkbox("##override", &mut overridden) {
+				unit.stats.0[i] = overridden.then_some(bases.0[i]);
+				edit = Some(before.clone());
+			}
+			self.next_column();
+			match &mut unit.stats.0[i] {
+				Some(stat) => {
+					self.set_next_item_width(-1.0);
+					self.input_int("##value", stat).step(0).build();
+					*stat = (*stat).clamp(0, MAX_STAT);
+					edit = edit.or(track_edit(self, &before, &mut map.inspector_edit));
+				}
+				None => self.text_disabled(bases.0[i].to_string()),
+			}
+			self.next_column();
+		}
+		self.columns(1, "##stats", false);
+
+		self.separator();
+		self.text("Inventory:");
+		let mut remove = None;
+		for (i, item) in unit.items.iter_mut().enumerate() {
+			let _id = self.push_id_usize(i);
+			let mut names: Vec<&str> = items.iter().map(|i| i.name.as_str()).collect();
+			// Keep items which aren't in the project selected, rather than silently changing them.
+			let missing = format!("Missing item ({item})");
+			let mut selected = match items.iter().position(|i| i.uuid == *item) {
+				Some(i) => i,
+				None => {
+					names.push(&missing);
+					names.len() - 1
+				}
+			};
+			if self.combo_simple_string("##item", &mut selected, &names) && selected < items.len() {
+				*item = items[selected].uuid;
+				edit = Some(before.clone());
+			}
+			self.same_line();
+			if self.small_button("Remove") {
+				remove = Some(i);
+			}
+		}
+		if let Some(i) = remove {
+			unit.items.remove(i);
+			edit = Some(before.clone());
+		}
+		match items.first() {
+			Some(first) => {
+				if self.button("Add Item") {
+					unit.items.push(first.uuid);
+					edit = Some(before.clone());
+				}
+			}
+			None => self.text_disabled("Create items in the Items window to add them."),
+		}
+		if self.checkbox("Drops last item", &mut unit.drops_item) {
+			edit = Some(before.clone());
+		}
+		self.hover_tooltip(
+			"Whether the unit's last item is given to the player when it's defeated",
+		);
+
+		if let Some(from) = edit {
+			let to = map.units[index].clone();
+			map.record(MapEdit::ModifyUnit { index, from, to });
+		}
+	}
 }
@@ -0,0 +1,105 @@
+use crate::toml_helpers::*;
+use fe_data::FeError;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use toml::*;
+
+/// A side which units can fight for, such as the player or their enemies.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Faction {
+	pub name: String,
+	/// The color units of this faction are tinted with.
+	pub color: [u8; 3],
+}
+
+impl Faction {
+	fn new(name: &str, color: [u8; 3]) -> Self {
+		Self {
+			name: name.to_string(),
+			color,
+		}
+	}
+
+	fn from_table(table: &Table) -> Result<Self, Box<dyn Error>> {
+		let name = get_string(table, "name")?;
+		let mut color = [0; 3];
+		let values = get_array(table, "color")?;
+		if values.len() != 3 {
+			Err(FeError::from(format!(
+				"{name}: color must have a red, green, and blue value"
+			)))?;
+		}
+		for (channel, value) in color.iter_mut().zip(values) {
+			*channel = match as_usize(value, "color")? {
+				value @ 0..=255 => value as u8,
+				_ => Err(FeError::from(format!(
+					"{name}: color values must be between 0 and 255"
+				)))?,
+			};
+		}
+		Ok(Self { name, color })
+	}
+
+	pub fn color_with_alpha(&self, alpha: f32) -> [f32; 4] {
+		let [r, g, b] = self.color.map(|c| c as f32 / 255.0);
+		[r, g, b, alpha]
+	}
+}
+
+/// The factions units can belong to, configured per project in `factions.toml`.
+pub struct Factions {
+	pub factions: Vec<Faction>,
+}
+
+impl Factions {
+	pub fn new() -> Self {
+		Self {
+			factions: vec![
+				Faction::new("Player", [64, 128, 255]),
+				Faction::new("Enemy", [255, 64, 64]),
+				Faction::new("Ally", [64, 200, 64]),
+				Faction::new("Other", [230, 200, 40]),
+			],
+		}
+	}
+
+	/// Reads the project's factions, or uses the default ones if it doesn't have any.
+	pub fn open(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+		let Ok(toml) = fs::read_to_string(path) else {
+			return Ok(Self::new());
+		};
+		let toml: Table = toml.parse()?;
+
+		let mut factions = Vec::new();
+		for faction in get_array(&toml, "faction")? {
+			let Value::Table(faction) = faction else {
+				Err(FeError::from("Faction is not a table"))?
+			};
+			let faction = Faction::from_table(faction)?;
+			if factions.iter().any(|f: &Faction| f.name == faction.name) {
+				Err(FeError::from(format!(
+					"There is more than one faction named {}",
+					faction.name
+				)))?;
+			}
+			factions.push(faction);
+		}
+		if factions.is_empty() {
+			Err(FeError::from("There must be at least one faction"))?;
+		}
+
+		Ok(Self { factions })
+	}
+
+	/// The faction given to new units, and to units saved before factions were added,
+	/// which is whichever is listed first.
+	pub fn default_faction(&self) -> &str {
+		// There's always at least one faction.
+		&self.factions[0].name
+	}
+
+	pub fn get(&self, name: &str) -> Option<&Faction> {
+		self.factions.iter().find(|f| f.name == name)
+	}
+}
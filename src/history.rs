@@ -0,0 +1,154 @@
+/// An undo/redo stack of reversible edits.
+///
+/// The history doesn't know how to apply its entries;
+/// editors pop them with `undo` and `redo` and apply them to their own data.
+#[derive(Debug)]
+pub struct History<T> {
+	undo_stack: Vec<T>,
+	redo_stack: Vec<T>,
+	/// The depth of the undo stack when the data was last saved,
+	/// or `None` if that state can no longer be reached.
+	saved_depth: Option<usize>,
+	/// Counts every push, undo and redo, so that anything derived from the data
+	/// can tell when it needs to be updated.
+	revision: usize,
+}
+
+impl<T> History<T> {
+	pub fn new() -> Self {
+		Self {
+			undo_stack: Vec::new(),
+			redo_stack: Vec::new(),
+			saved_depth: Some(0),
+			revision: 0,
+		}
+	}
+
+	/// Records an edit which has already been applied.
+	/// This discards anything that could have been redone.
+	pub fn push(&mut self, edit: T) {
+		// If the saved state was undone, it's about to be lost along with the redo stack.
+		if self.saved_depth > Some(self.undo_stack.len()) {
+			self.saved_depth = None;
+		}
+		self.undo_stack.push(edit);
+		self.redo_stack.clear();
+		self.revision += 1;
+	}
+
+	/// Returns the edit which should be reverted, if any.
+	pub fn undo(&mut self) -> Option<&T> {
+		let edit = self.undo_stack.pop()?;
+		self.redo_stack.push(edit);
+		self.revision += 1;
+		self.redo_stack.last()
+	}
+
+	/// Returns the edit which should be reapplied, if any.
+	pub fn redo(&mut self) -> Option<&T> {
+		let edit = self.redo_stack.pop()?;
+		self.undo_stack.push(edit);
+		self.revision += 1;
+		self.undo_stack.last()
+	}
+
+	pub fn can_undo(&self) -> bool {
+		!self.undo_stack.is_empty()
+	}
+
+	pub fn can_redo(&self) -> bool {
+		!self.redo_stack.is_empty()
+	}
+
+	pub fn revision(&self) -> usize {
+		self.revision
+	}
+
+	pub fn mark_saved(&mut self) {
+		self.saved_depth = Some(self.undo_stack.len());
+	}
+
+	/// Returns true if the history is at the same point as when it was last saved.
+	pub fn is_saved(&self) -> bool {
+		self.saved_depth == Some(self.undo_stack.len())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn undo_and_redo_return_edits_in_order() {
+		let mut history = History::new();
+		history.push(1);
+		history.push(2);
+
+		assert_eq!(history.undo(), Some(&2));
+		assert_eq!(history.undo(), Some(&1));
+		assert_eq!(history.undo(), None);
+		assert_eq!(history.redo(), Some(&1));
+		assert_eq!(history.redo(), Some(&2));
+		assert_eq!(history.redo(), None);
+	}
+
+	#[test]
+	fn push_discards_redo() {
+		let mut history = History::new();
+		history.push(1);
+		history.undo();
+		assert!(history.can_redo());
+
+		history.push(2);
+		assert!(!history.can_redo());
+		assert_eq!(history.undo(), Some(&2));
+		assert!(!history.can_undo());
+	}
+
+	#[test]
+	fn undoing_back_to_the_save_is_saved() {
+		let mut history = History::new();
+		history.push(1);
+		history.mark_saved();
+		history.push(2);
+		assert!(!history.is_saved());
+
+		history.undo();
+		assert!(history.is_saved());
+		history.undo();
+		assert!(!history.is_saved());
+		history.redo();
+		assert!(history.is_saved());
+	}
+
+	#[test]
+	fn save_is_lost_once_it_cannot_be_redone() {
+		let mut history = History::new();
+		history.push(1);
+		history.mark_saved();
+		history.undo();
+		history.push(2);
+
+		history.undo();
+		assert!(!history.is_saved());
+		history.redo();
+		assert!(!history.is_saved());
+	}
+
+	#[test]
+	fn revision_changes_with_every_edit() {
+		let mut history = History::new();
+		let start = history.revision();
+		history.push(1);
+		let pushed = history.revision();
+		assert_ne!(pushed, start);
+		history.undo();
+		assert_ne!(history.revision(), pushed);
+		assert_ne!(history.revision(), start);
+
+		// Nothing to undo or redo isn't a change.
+		let revision = history.revision();
+		history.undo();
+		assert_eq!(history.revision(), revision);
+	}
+}
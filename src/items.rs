@@ -0,0 +1,694 @@
+use crate::toml_helpers::*;
+use crate::*;
+use fe_data::FeError;
+use imgui::*;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::*;
+use uuid::Uuid;
+
+pub type ItemIcons = HashMap<PathBuf, TextureId>;
+
+/// The largest might, hit, crit, or weight an item can have.
+pub const MAX_ITEM_STAT: i32 = 255;
+pub const MAX_RANGE: u32 = 15;
+pub const MAX_USES: u32 = 99;
+
+/// What an item is used for, which decides which of its fields matter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ItemKind {
+	Weapon,
+	Staff,
+	Consumable,
+}
+
+impl ItemKind {
+	pub const ALL: [ItemKind; 3] = [ItemKind::Weapon, ItemKind::Staff, ItemKind::Consumable];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			ItemKind::Weapon => "Weapon",
+			ItemKind::Staff => "Staff",
+			ItemKind::Consumable => "Consumable",
+		}
+	}
+
+	pub fn from_name(name: &str) -> Option<Self> {
+		Self::ALL.into_iter().find(|k| k.name() == name)
+	}
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WeaponType {
+	Sword,
+	Lance,
+	Axe,
+	Bow,
+	Anima,
+	Light,
+	Dark,
+	Staff,
+}
+
+impl WeaponType {
+	pub const ALL: [WeaponType; 8] = [
+		WeaponType::Sword,
+		WeaponType::Lance,
+		WeaponType::Axe,
+		WeaponType::Bow,
+		WeaponType::Anima,
+		WeaponType::Light,
+		WeaponType::Dark,
+		WeaponType::Staff,
+	];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			WeaponType::Sword => "Sword",
+			WeaponType::Lance => "Lance",
+			WeaponType::Axe => "Axe",
+			WeaponType::Bow => "Bow",
+			WeaponType::Anima => "Anima",
+			WeaponType::Light => "Light",
+			WeaponType::Dark => "Dark",
+			WeaponType::Staff => "Staff",
+		}
+	}
+
+	pub fn from_name(name: &str) -> Option<Self> {
+		Self::ALL.into_iter().find(|t| t.name() == name)
+	}
+}
+
+/// How skilled a unit must be with a weapon type to use an item, from E (the lowest) to S.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WeaponRank {
+	E,
+	D,
+	C,
+	B,
+	A,
+	S,
+}
+
+impl WeaponRank {
+	pub const ALL: [WeaponRank; 6] = [
+		WeaponRank::E,
+		WeaponRank::D,
+		WeaponRank::C,
+		WeaponRank::B,
+		WeaponRank::A,
+		WeaponRank::S,
+	];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			WeaponRank::E => "E",
+			WeaponRank::D => "D",
+			WeaponRank::C => "C",
+			WeaponRank::B => "B",
+			WeaponRank::A => "A",
+			WeaponRank::S => "S",
+		}
+	}
+
+	pub fn from_name(name: &str) -> Option<Self> {
+		Self::ALL.into_iter().find(|r| r.name() == name)
+	}
+}
+
+/// A weapon, staff, or consumable which units can carry.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Item {
+	pub name: String,
+	/// Units refer to items by UUID, so that items can be renamed.
+	pub uuid: Uuid,
+	pub desc: String,
+	pub icon: Option<PathBuf>,
+	pub kind: ItemKind,
+	/// Only used by weapons, since staves are always `WeaponType::Staff`.
+	pub weapon_type: WeaponType,
+	/// The weapon rank needed to use the item.
+	pub rank: WeaponRank,
+	pub might: i32,
+	pub hit: i32,
+	pub crit: i32,
+	pub weight: i32,
+	/// The closest and furthest distances the item can be used at.
+	pub min_range: u32,
+	pub max_range: u32,
+	/// How many times the item can be used before it breaks.
+	pub uses: u32,
+	pub price: u32,
+}
+
+impl Item {
+	pub fn new() -> Self {
+		Self {
+			name: String::new(),
+			uuid: Uuid::new_v4(),
+			desc: String::new(),
+			icon: None,
+			kind: ItemKind::Weapon,
+			weapon_type: WeaponType::Sword,
+			rank: WeaponRank::E,
+			might: 0,
+			hit: 100,
+			crit: 0,
+			weight: 0,
+			min_range: 1,
+			max_range: 1,
+			uses: 1,
+			price: 0,
+		}
+	}
+
+	/// The weapon type a unit needs to be proficient in to use the item, if any.
+	pub fn required_weapon_type(&self) -> Option<WeaponType> {
+		match self.kind {
+			ItemKind::Weapon => Some(self.weapon_type),
+			ItemKind::Staff => Some(WeaponType::Staff),
+			ItemKind::Consumable => None,
+		}
+	}
+
+	pub fn from_table(table: &Table) -> Result<Self, Box<dyn Error>> {
+		let name = get_string(table, "name")?;
+		let mut item = Self {
+			name: name.clone(),
+			..Self::new()
+		};
+		// Items saved before they had UUIDs are given a new one.
+		match table.get("uuid") {
+			Some(Value::String(uuid)) => match Uuid::parse_str(uuid) {
+				Ok(uuid) => item.uuid = uuid,
+				Err(msg) => Err(FeError::from(format!("{name}: Invalid UUID: {msg}")))?,
+			},
+			Some(_) => Err(FeError::from(format!("{name}: UUID is not a string")))?,
+			None => {}
+		}
+
+		// Every key but the name is optional.
+		let get_name = |key: &str| -> Result<Option<String>, Box<dyn Error>> {
+			if !table.contains_key(key) {
+				return Ok(None);
+			}
+			match get_string(table, key) {
+				Ok(value) => Ok(Some(value)),
+				Err(msg) => Err(FeError::from(format!("{name}: {msg}")))?,
+			}
+		};
+		if let Some(kind) = get_name("kind")? {
+			item.kind = ItemKind::from_name(&kind)
+				.ok_or_else(|| FeError::from(format!("{name}: Unknown item kind \"{kind}\"")))?;
+		}
+		if let Some(weapon_type) = get_name("weapon_type")? {
+			item.weapon_type = WeaponType::from_name(&weapon_type).ok_or_else(|| {
+				FeError::from(format!("{name}: Unknown weapon type \"{weapon_type}\""))
+			})?;
+		}
+		if let Some(rank) = get_name("rank")? {
+			item.rank = WeaponRank::from_name(&rank)
+				.ok_or_else(|| FeError::from(format!("{name}: Unknown weapon rank \"{rank}\"")))?;
+		}
+		item.desc = get_name("desc")?.unwrap_or_default();
+		item.icon = get_name("icon")?.map(PathBuf::from);
+
+		for (key, field) in [
+			("might", &mut item.might),
+			("hit", &mut item.hit),
+			("crit", &mut item.crit),
+			("weight", &mut item.weight),
+		] {
+			if table.contains_key(key) {
+				*field = get_usize(table, key)?.min(MAX_ITEM_STAT as usize) as i32;
+			}
+		}
+		for (key, field) in [
+			("min_range", &mut item.min_range),
+			("max_range", &mut item.max_range),
+			("uses", &mut item.uses),
+			("price", &mut item.price),
+		] {
+			if table.contains_key(key) {
+				*field = u32::try_from(get_usize(table, key)?)
+					.map_err(|_| FeError::from(format!("{name}: {key} is too large")))?;
+			}
+		}
+		item.clamp();
+		Ok(item)
+	}
+
+	pub fn to_table(&self) -> Table {
+		let mut table = Table::new();
+		table.insert("name".into(), Value::String(self.name.clone()));
+		table.insert("uuid".into(), Value::String(self.uuid.to_string()));
+		table.insert("desc".into(), Value::String(self.desc.clone()));
+		if let Some(icon) = &self.icon {
+			table.insert(
+				"icon".into(),
+				Value::String(icon.to_string_lossy().to_string()),
+			);
+		}
+		table.insert("kind".into(), Value::String(self.kind.name().into()));
+		table.insert(
+			"weapon_type".into(),
+			Value::String(self.weapon_type.name().into()),
+		);
+		table.insert("rank".into(), Value::String(self.rank.name().into()));
+		for (key, value) in [
+			("might", self.might),
+			("hit", self.hit),
+			("crit", self.crit),
+			("weight", self.weight),
+		] {
+			table.insert(key.into(), Value::Integer(value as i64));
+		}
+		for (key, value) in [
+			("min_range", self.min_range),
+			("max_range", self.max_range),
+			("uses", self.uses),
+			("price", self.price),
+		] {
+			table.insert(key.into(), usize_value(value as usize));
+		}
+		table
+	}
+
+	/// Keeps every field within its limits, and the range from ending before it starts.
+	fn clamp(&mut self) {
+		for stat in [
+			&mut self.might,
+			&mut self.hit,
+			&mut self.crit,
+			&mut self.weight,
+		] {
+			*stat = (*stat).clamp(0, MAX_ITEM_STAT);
+		}
+		self.min_range = self.min_range.clamp(1, MAX_RANGE);
+		self.max_range = self.max_range.clamp(self.min_range, MAX_RANGE);
+		self.uses = self.uses.clamp(1, MAX_USES);
+	}
+
+	/// Draws the item's fields.
+	/// Returns the item's previous state if an edit was completed this frame.
+	fn editor(
+		&mut self,
+		ui: &Ui,
+		item_icons: &ItemIcons,
+		missing: &MissingTextures,
+		edit_start: &mut Option<Item>,
+	) -> Option<Item> {
+		let before = self.clone();
+		let mut edit = None;
+
+		ui.input_text("##name", &mut self.name).hint("Name").build();
+		edit = edit.or(track_edit(ui, &before, edit_start));
+		let clicked = match &self.icon {
+			Some(icon) => ui.image_button("##icon", missing.icon(item_icons, icon), [32.0; 2]),
+			None => ui.button_with_size("?##icon", [32.0; 2]),
+		};
+		if clicked {
+			ui.open_popup("Select Icon");
+		}
+		ui.hover_tooltip("Click to select item icon");
+
+		ui.text("Description:");
+		ui.input_text_multiline(
+			"##desc",
+			&mut self.desc,
+			[ui.content_region_avail()[0], 64.0],
+		)
+		.build();
+		edit = edit.or(track_edit(ui, &before, edit_start));
+
+		let mut kind = ItemKind::ALL
+			.iter()
+			.position(|k| *k == self.kind)
+			.unwrap_or(0);
+		let names = ItemKind::ALL.map(|k| k.name());
+		if ui.combo_simple_string("Kind", &mut kind, &names) {
+			self.kind = ItemKind::ALL[kind];
+			edit = Some(before.clone());
+		}
+		if self.kind == ItemKind::Weapon {
+			// Staves are their own kind of item.
+			let types = &WeaponType::ALL[..WeaponType::ALL.len() - 1];
+			let mut selected = types
+				.iter()
+				.position(|t| *t == self.weapon_type)
+				.unwrap_or(0);
+			let names: Vec<&str> = types.iter().map(|t| t.name()).collect();
+			if ui.combo_simple_string("Type", &mut selected, &names) {
+				self.weapon_type = types[selected];
+				edit = Some(before.clone());
+			}
+		}
+		if self.kind != ItemKind::Consumable {
+			let mut rank = WeaponRank::ALL
+				.iter()
+				.position(|r| *r == self.rank)
+				.unwrap_or(0);
+			let names = WeaponRank::ALL.map(|r| r.name());
+			if ui.combo_simple_string("Rank", &mut rank, &names) {
+				self.rank = WeaponRank::ALL[rank];
+				edit = Some(before.clone());
+			}
+
+			if self.kind == ItemKind::Weapon {
+				for (label, stat) in [
+					("Might", &mut self.might),
+					("Hit", &mut self.hit),
+					("Crit", &mut self.crit),
+				] {
+					ui.input_int(label, stat).build();
+					edit = edit.or(track_edit(ui, &before, edit_start));
+				}
+			}
+			ui.input_int("Weight", &mut self.weight).build();
+			edit = edit.or(track_edit(ui, &before, edit_start));
+			ui.input_scalar("Min Range", &mut self.min_range)
+				.step(1)
+				.build();
+			edit = edit.or(track_edit(ui, &before, edit_start));
+			ui.input_scalar("Max Range", &mut self.max_range)
+				.step(1)
+				.build();
+			edit = edit.or(track_edit(ui, &before, edit_start));
+		}
+		ui.input_scalar("Uses", &mut self.uses).step(1).build();
+		edit = edit.or(track_edit(ui, &before, edit_start));
+		ui.input_scalar("Price", &mut self.price).step(100).build();
+		edit = edit.or(track_edit(ui, &before, edit_start));
+		self.clamp();
+
+		ui.popup("Select Icon", || {
+			ui.text("Select an icon");
+			if item_icons.is_empty() {
+				ui.text_disabled("There are no icons in item-icons.");
+			}
+			for (i, (path, texture)) in item_icons.iter().enumerate() {
+				// Icons per row.
+				if i % 3 != 0 {
+					ui.same_line();
+				}
+				if ui.image_button(i.to_string(), *texture, [32.0; 2]) {
+					edit = Some(self.clone());
+					self.icon = Some(path.clone());
+				}
+				ui.hover_tooltip(&path.to_string_lossy());
+			}
+		});
+
+		edit
+	}
+}
+
+/// A reversible change to the item list.
+#[derive(Debug)]
+pub enum ItemEdit {
+	Insert(usize, Item),
+	Remove(usize, Item),
+	Modify { index: usize, from: Item, to: Item },
+}
+
+impl ItemEdit {
+	pub fn apply(&self, items: &mut Vec<Item>) {
+		match self {
+			ItemEdit::Insert(index, item) => items.insert(*index, item.clone()),
+			ItemEdit::Remove(index, _) => {
+				items.remove(*index);
+			}
+			ItemEdit::Modify { index, to, .. } => items[*index] = to.clone(),
+		}
+	}
+
+	pub fn revert(&self, items: &mut Vec<Item>) {
+		match self {
+			ItemEdit::Insert(index, _) => {
+				items.remove(*index);
+			}
+			ItemEdit::Remove(index, item) => items.insert(*index, item.clone()),
+			ItemEdit::Modify { index, from, .. } => items[*index] = from.clone(),
+		}
+	}
+}
+
+pub struct ItemEditor {
+	pub save_state: SaveState,
+	pub items: Vec<Item>,
+	pub history: History<ItemEdit>,
+	/// Whether the item window is focused this frame; used to direct undo and redo.
+	pub focused: bool,
+	pub search_field: String,
+	/// The item's state when the current text edit began.
+	edit_start: Option<Item>,
+	/// An item to open and scroll to on the next frame.
+	reveal: Option<Uuid>,
+}
+
+impl ItemEditor {
+	/// Opens an item file, or starts with no items if it doesn't exist.
+	pub fn open(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+		let mut items = Vec::new();
+
+		if let Ok(toml) = fs::read_to_string(path) {
+			let toml: Table = toml.parse()?;
+			if toml.contains_key("item") {
+				for item in get_array(&toml, "item")? {
+					let Value::Table(item) = item else {
+						Err(FeError::from("Item is not a table"))?
+					};
+					items.push(Item::from_table(item)?);
+				}
+			}
+		}
+
+		Ok(Self {
+			save_state: SaveState::new(),
+			items,
+			history: History::new(),
+			focused: false,
+			search_field: String::new(),
+			edit_start: None,
+			reveal: None,
+		})
+	}
+
+	pub fn to_toml(&self) -> Result<String, Box<dyn Error>> {
+		let mut items = Vec::new();
+		for item in &self.items {
+			if item.name.is_empty() {
+				Err(FeError::from("Items must have a name"))?;
+			}
+			items.push(Value::Table(item.to_table()));
+		}
+
+		let mut toml = Table::new();
+		toml.insert("item".into(), Value::Array(items));
+		Ok(toml::to_string(&toml)?)
+	}
+
+	/// Looks up an item by its UUID.
+	pub fn get(&self, uuid: Uuid) -> Option<&Item> {
+		self.items.iter().find(|i| i.uuid == uuid)
+	}
+
+	fn push(&mut self, edit: ItemEdit) {
+		self.history.push(edit);
+		self.save_state.sync_with(&self.history);
+	}
+
+	fn apply(&mut self, edit: ItemEdit) {
+		edit.apply(&mut self.items);
+		self.push(edit);
+	}
+
+	pub fn undo(&mut self) {
+		self.edit_start = None;
+		if let Some(edit) = self.history.undo() {
+			edit.revert(&mut self.items);
+			self.save_state.sync_with(&self.history);
+		}
+	}
+
+	pub fn redo(&mut self) {
+		self.edit_start = None;
+		if let Some(edit) = self.history.redo() {
+			edit.apply(&mut self.items);
+			self.save_state.sync_with(&self.history);
+		}
+	}
+
+	pub fn mark_saved(&mut self) {
+		self.save_state.mark_saved();
+		self.history.mark_saved();
+	}
+
+	/// Opens an item's entry and scrolls to it, such as to show a problem with it.
+	pub fn reveal(&mut self, uuid: Uuid) {
+		self.search_field.clear();
+		self.reveal = Some(uuid);
+	}
+
+	pub fn draw(
+		&mut self,
+		ui: &Ui,
+		position: (f32, f32),
+		item_icons: &ItemIcons,
+		missing: &MissingTextures,
+	) {
+		self.focused = false;
+		let reveal = self.reveal.take();
+		let mut window = ui
+			.window("Items")
+			.position([position.0, position.1], Condition::FirstUseEver)
+			.size([250.0, 400.0], Condition::FirstUseEver)
+			.focus_on_appearing(false)
+			.collapsed(true, Condition::FirstUseEver)
+			.unsaved_document(!self.save_state.is_saved());
+		if reveal.is_some() {
+			window = window.collapsed(false, Condition::Always).focused(true);
+		}
+		window.build(|| {
+			self.focused =
+				ui.is_window_focused_with_flags(WindowFocusedFlags::ROOT_AND_CHILD_WINDOWS);
+
+			ui.text("Search:");
+			ui.input_text("##search", &mut self.search_field).build();
+
+			ui.separator();
+
+			let query = self.search_field.to_ascii_lowercase();
+			let mut edits = Vec::new();
+			for (index, item) in self.items.iter_mut().enumerate() {
+				if !item.name.to_ascii_lowercase().contains(&query) {
+					continue;
+				}
+
+				let _id = ui.push_id(item.uuid.to_string());
+
+				let revealed = reveal == Some(item.uuid);
+				if revealed {
+					ui.set_scroll_here_y();
+				}
+
+				let label = if item.name.is_empty() {
+					String::from("New item")
+				} else {
+					item.name.clone()
+				};
+				ui.tree_node_config("##header")
+					.label::<String, String>(label)
+					.framed(true)
+					.opened(
+						item.name.is_empty() || revealed,
+						if revealed {
+							Condition::Always
+						} else {
+							Condition::FirstUseEver
+						},
+					)
+					.build(|| {
+						if let Some(from) =
+							item.editor(ui, item_icons, missing, &mut self.edit_start)
+						{
+							edits.push(ItemEdit::Modify {
+								index,
+								from,
+								to: item.clone(),
+							});
+						}
+						if ui.button("Delete") {
+							ui.open_popup("Delete");
+						}
+
+						ui.modal_popup_config("Delete").build(|| {
+							ui.text(format!("Do you really want to delete \"{}\"?", item.name));
+							if ui.button("Cancel") {
+								ui.close_current_popup();
+							}
+							ui.same_line();
+							if ui.button("Delete") {
+								ui.close_current_popup();
+								edits.push(ItemEdit::Remove(index, item.clone()));
+							}
+						});
+					});
+
+				ui.separator();
+			}
+
+			for edit in edits {
+				// Modifications have already been made by the UI.
+				if let ItemEdit::Remove(..) = edit {
+					self.apply(edit);
+				} else {
+					self.push(edit);
+				}
+			}
+
+			if ui.button("Create new item") {
+				self.apply(ItemEdit::Insert(self.items.len(), Item::new()));
+			}
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_helpers::TempFile;
+
+	#[test]
+	fn items_survive_saving() {
+		let file = TempFile::new();
+		let mut editor = ItemEditor::open(&file.0).unwrap();
+		assert!(editor.items.is_empty());
+		let sword = Item {
+			name: String::from("Iron Sword"),
+			icon: Some(PathBuf::from("sword.png")),
+			might: 5,
+			weight: 5,
+			uses: 46,
+			price: 460,
+			..Item::new()
+		};
+		let heal = Item {
+			name: String::from("Heal"),
+			desc: String::from("Restores HP."),
+			kind: ItemKind::Staff,
+			rank: WeaponRank::D,
+			uses: 30,
+			..Item::new()
+		};
+		editor.items = vec![sword, heal];
+
+		fs::write(&file.0, editor.to_toml().unwrap()).unwrap();
+		// Units refer to items by UUID, so they have to be kept.
+		assert_eq!(ItemEditor::open(&file.0).unwrap().items, editor.items);
+	}
+
+	#[test]
+	fn items_without_uuids_are_given_one() {
+		let file = TempFile::new();
+		fs::write(
+			&file.0,
+			"[[item]]\nname = \"Vulnerary\"\nkind = \"Consumable\"\n",
+		)
+		.unwrap();
+		let items = ItemEditor::open(&file.0).unwrap().items;
+		assert_eq!(items.len(), 1);
+		assert_eq!(items[0].kind, ItemKind::Consumable);
+		assert!(!items[0].uuid.is_nil());
+	}
+
+	#[test]
+	fn unnamed_items_are_not_saved() {
+		let mut editor = ItemEditor::open(&TempFile::new().0).unwrap();
+		editor.items.push(Item::new());
+		assert!(editor.to_toml().is_err());
+	}
+}
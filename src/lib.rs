@@ -1,18 +1,39 @@
+mod characters;
 mod classes;
 mod custom_ui;
+mod faction;
+mod history;
+mod items;
+mod lint;
 mod map;
+mod progression;
+mod stats;
+mod terrain;
 mod texture_loader;
+mod toml_helpers;
+mod watcher;
 
+pub mod cli;
 pub mod support;
 
+pub use characters::*;
 pub use classes::*;
 pub use custom_ui::*;
+pub use faction::*;
 pub use fe_data::*;
+pub use history::*;
+pub use items::*;
+pub use lint::*;
 pub use map::*;
+pub use progression::*;
+pub use stats::*;
+pub use terrain::*;
 pub use texture_loader::*;
+pub use watcher::*;
 
 pub use glium::backend::Facade;
 
+#[derive(Debug)]
 pub struct SaveState {
 	saved: bool,
 	autosaved: bool,
@@ -39,6 +60,16 @@ impl SaveState {
 		self.saved = true;
 	}
 
+	/// Marks the state as saved if `history` is back to where it was last saved,
+	/// such as after undoing every change since then.
+	pub fn sync_with<T>(&mut self, history: &History<T>) {
+		if history.is_saved() {
+			self.mark_saved();
+		} else {
+			self.mark_unsaved();
+		}
+	}
+
 	pub fn is_saved(&self) -> bool {
 		self.saved
 	}
@@ -47,3 +78,24 @@ impl SaveState {
 		self.autosaved || self.saved
 	}
 }
+
+#[cfg(test)]
+pub(crate) mod test_helpers {
+	use std::path::PathBuf;
+
+	/// A file for tests to save to, which is removed once they're done with it.
+	pub struct TempFile(pub PathBuf);
+
+	impl TempFile {
+		pub fn new() -> Self {
+			let name = format!("fe-editor-{}.toml", uuid::Uuid::new_v4());
+			Self(std::env::temp_dir().join(name))
+		}
+	}
+
+	impl Drop for TempFile {
+		fn drop(&mut self) {
+			let _ = std::fs::remove_file(&self.0);
+		}
+	}
+}
@@ -0,0 +1,481 @@
+use crate::*;
+use std::fmt;
+use std::path::Path;
+use uuid::Uuid;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+	Error,
+	Warning,
+}
+
+/// Where a problem is, so that the editor can jump to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProblemLocation {
+	Tile {
+		map: String,
+		/// The layer the problem is on, if it's a tile rather than a unit or spawn.
+		layer: Option<usize>,
+		x: usize,
+		y: usize,
+	},
+	Class(Uuid),
+	Item(Uuid),
+	Character(Uuid),
+}
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+	pub severity: Severity,
+	/// The file the problem is in, relative to the project.
+	pub file: String,
+	pub location: Option<ProblemLocation>,
+	pub message: String,
+}
+
+impl Diagnostic {
+	fn error(file: String, location: ProblemLocation, message: String) -> Self {
+		Self {
+			severity: Severity::Error,
+			file,
+			location: Some(location),
+			message,
+		}
+	}
+
+	fn warning(file: String, location: ProblemLocation, message: String) -> Self {
+		Self {
+			severity: Severity::Warning,
+			file,
+			location: Some(location),
+			message,
+		}
+	}
+}
+
+impl fmt::Display for Diagnostic {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match &self.location {
+			Some(ProblemLocation::Tile { x, y, .. }) => {
+				write!(f, "{} ({x}, {y}): {}", self.file, self.message)
+			}
+			_ => write!(f, "{}: {}", self.file, self.message),
+		}
+	}
+}
+
+/// Checks for classes without a unique name, with invalid stats, icons which aren't in `class-icons/`,
+/// unknown movement classes, or promotions which are missing or lead back to the class.
+pub fn lint_classes(
+	classes: &[ClassEntry],
+	items: &[Item],
+	movement_classes: &[String],
+	has_icon: impl Fn(&Path) -> bool,
+) -> Vec<Diagnostic> {
+	let mut problems = Vec::new();
+	let file = String::from("classes.toml");
+	let cycles = promotion_cycles(classes);
+
+	for (i, class) in classes.iter().enumerate() {
+		let location = ProblemLocation::Class(class.uuid);
+		// Classes are saved as tables named after them, so names must be unique.
+		if class.data.name.is_empty() {
+			problems.push(Diagnostic::error(
+				file.clone(),
+				location.clone(),
+				String::from("Class has no name"),
+			));
+		} else if classes[..i]
+			.iter()
+			.any(|other| other.data.name == class.data.name)
+		{
+			problems.push(Diagnostic::error(
+				file.clone(),
+				location.clone(),
+				format!("More than one class is named \"{}\"", class.data.name),
+			));
+		}
+		let progression = &class.progression;
+		if !progression.movement.is_empty() && !movement_classes.contains(&progression.movement) {
+			problems.push(Diagnostic::error(
+				file.clone(),
+				location.clone(),
+				format!(
+					"{}: movement class \"{}\" does not exist",
+					class.data.name, progression.movement
+				),
+			));
+		}
+		for promotion in &progression.promotions {
+			if !classes.iter().any(|c| c.uuid == promotion.class) {
+				problems.push(Diagnostic::error(
+					file.clone(),
+					location.clone(),
+					format!(
+						"{}: promotes into a class which does not exist",
+						class.data.name
+					),
+				));
+			}
+			if let Some(item) = promotion.item {
+				if !items.iter().any(|i| i.uuid == item) {
+					problems.push(Diagnostic::warning(
+						file.clone(),
+						location.clone(),
+						format!(
+							"{}: promotion item ({item}) does not exist",
+							class.data.name
+						),
+					));
+				}
+			}
+		}
+		if cycles.contains(&class.uuid) {
+			problems.push(Diagnostic::error(
+				file.clone(),
+				location.clone(),
+				format!("{}: promotions lead back into this class", class.data.name),
+			));
+		}
+		if let Err(msg) = class.stats.validate() {
+			problems.push(Diagnostic::error(
+				file.clone(),
+				location.clone(),
+				format!("{}: {msg}", class.data.name),
+			));
+		}
+		if !has_icon(&class.data.texture) {
+			problems.push(Diagnostic::error(
+				file.clone(),
+				location,
+				format!(
+					"{}: icon \"{}\" is not in class-icons",
+					class.data.name,
+					class.data.texture.display()
+				),
+			));
+		}
+	}
+
+	problems
+}
+
+/// Checks for items without a unique name, or with icons which aren't in `item-icons/`.
+pub fn lint_items(items: &[Item], has_icon: impl Fn(&Path) -> bool) -> Vec<Diagnostic> {
+	let mut problems = Vec::new();
+	let file = String::from("items.toml");
+
+	for (i, item) in items.iter().enumerate() {
+		let location = ProblemLocation::Item(item.uuid);
+		if item.name.is_empty() {
+			problems.push(Diagnostic::error(
+				file.clone(),
+				location.clone(),
+				String::from("Item has no name"),
+			));
+		} else if items[..i].iter().any(|other| other.name == item.name) {
+			problems.push(Diagnostic::warning(
+				file.clone(),
+				location.clone(),
+				format!("More than one item is named \"{}\"", item.name),
+			));
+		}
+		if let Some(icon) = &item.icon {
+			if !has_icon(icon) {
+				problems.push(Diagnostic::error(
+					file.clone(),
+					location,
+					format!(
+						"{}: icon \"{}\" is not in item-icons",
+						item.name,
+						icon.display()
+					),
+				));
+			}
+		}
+	}
+
+	problems
+}
+
+/// Checks for characters without a name, with a default class which doesn't exist,
+/// or with portraits which aren't in `portraits/`.
+pub fn lint_characters(
+	characters: &[Character],
+	classes: &[ClassEntry],
+	has_portrait: impl Fn(&Path) -> bool,
+) -> Vec<Diagnostic> {
+	let mut problems = Vec::new();
+	let file = String::from("characters.toml");
+
+	for character in characters {
+		let location = ProblemLocation::Character(character.uuid);
+		let name = if character.name.is_empty() {
+			"Character"
+		} else {
+			&character.name
+		};
+		if character.name.is_empty() {
+			problems.push(Diagnostic::error(
+				file.clone(),
+				location.clone(),
+				String::from("Character has no name"),
+			));
+		}
+		if let Some(class) = character.class {
+			if !classes.iter().any(|c| c.uuid == class) {
+				problems.push(Diagnostic::error(
+					file.clone(),
+					location.clone(),
+					format!("{name}'s default class is missing ({class})"),
+				));
+			}
+		}
+		if let Some(portrait) = &character.portrait {
+			if !has_portrait(portrait) {
+				problems.push(Diagnostic::error(
+					file.clone(),
+					location,
+					format!(
+						"{name}: portrait \"{}\" is not in portraits",
+						portrait.display()
+					),
+				));
+			}
+		}
+	}
+
+	problems
+}
+
+/// Checks for tiles past the end of the tileset, units with missing classes, characters,
+/// factions or items,
+/// units with stats above their class's caps, and units or spawns which are outside of the map
+/// or on top of each other.
+pub fn lint_map(
+	map: &MapEditor,
+	classes: &[ClassEntry],
+	items: &[Item],
+	characters: &[Character],
+	factions: &Factions,
+	tile_count: usize,
+) -> Vec<Diagnostic> {
+	let mut problems = Vec::new();
+	let name = &map.data.name;
+	let file = format!("maps/{name}.toml");
+	let width = map.data.width;
+	let height = map.data.height;
+	let tile = |layer, x: u32, y: u32| ProblemLocation::Tile {
+		map: name.clone(),
+		layer,
+		x: x as usize,
+		y: y as usize,
+	};
+	let in_bounds = |x: u32, y: u32| (x as usize) < width && (y as usize) < height;
+
+	// Only the first bad tile on each layer is reported, since a bad tileset may cause thousands.
+	for (i, layer) in map.layers.iter().enumerate() {
+		let tiles = map.tiles(i);
+		let is_missing = |tile: &usize| *tile != EMPTY_TILE && *tile >= tile_count;
+		if let Some(pos) = tiles.iter().position(is_missing) {
+			let count = tiles.iter().filter(|t| is_missing(t)).count();
+			problems.push(Diagnostic::error(
+				file.clone(),
+				tile(Some(i), (pos % width) as u32, (pos / width) as u32),
+				format!(
+					"{count} tile(s) on layer \"{}\" are not in the tileset, such as {}",
+					layer.name, tiles[pos]
+				),
+			));
+		}
+	}
+
+	for (i, unit) in map.units.iter().enumerate() {
+		let location = tile(None, unit.x, unit.y);
+		let unit_name = match unit.display_name(characters) {
+			"" => "Unit",
+			name => name,
+		};
+		if let Some(character) = unit.character {
+			if !characters.iter().any(|c| c.uuid == character) {
+				problems.push(Diagnostic::warning(
+					file.clone(),
+					location.clone(),
+					format!("Unit refers to a missing character ({character})"),
+				));
+			}
+		}
+		match classes.iter().find(|c| c.uuid == unit.class) {
+			Some(class) => {
+				let bases = unit
+					.base_stats(classes, characters)
+					.unwrap_or(class.stats.bases);
+				let stats = unit.stats.apply(&bases);
+				for (i, stat) in STAT_NAMES.iter().enumerate() {
+					let (value, cap) = (stats.0[i], class.stats.caps.0[i]);
+					if value > cap {
+						problems.push(Diagnostic::warning(
+							file.clone(),
+							location.clone(),
+							format!(
+								"{unit_name}'s {stat} ({value}) is above {}'s cap ({cap})",
+								class.data.name
+							),
+						));
+					}
+				}
+			}
+			None => problems.push(Diagnostic::error(
+				file.clone(),
+				location.clone(),
+				format!("{unit_name} refers to a missing class ({})", unit.class),
+			)),
+		}
+		if factions.get(&unit.faction).is_none() {
+			problems.push(Diagnostic::warning(
+				file.clone(),
+				location.clone(),
+				format!(
+					"{unit_name} belongs to an unknown faction ({})",
+					unit.faction
+				),
+			));
+		}
+		for item in &unit.items {
+			if !items.iter().any(|i| i.uuid == *item) {
+				problems.push(Diagnostic::warning(
+					file.clone(),
+					location.clone(),
+					format!("{unit_name} carries an item which does not exist ({item})"),
+				));
+			}
+		}
+		if !in_bounds(unit.x, unit.y) {
+			problems.push(Diagnostic::error(
+				file.clone(),
+				location.clone(),
+				format!("{unit_name} is outside of the map"),
+			));
+		}
+		if map.units[..i]
+			.iter()
+			.any(|u| (u.x, u.y) == (unit.x, unit.y))
+		{
+			problems.push(Diagnostic::warning(
+				file.clone(),
+				location,
+				format!("{unit_name} is on the same tile as another unit"),
+			));
+		}
+	}
+
+	for (i, &(x, y)) in map.data.spawns.iter().enumerate() {
+		if !in_bounds(x, y) {
+			problems.push(Diagnostic::error(
+				file.clone(),
+				tile(None, x, y),
+				String::from("Spawn is outside of the map"),
+			));
+		}
+		if map.data.spawns[..i].contains(&(x, y)) {
+			problems.push(Diagnostic::warning(
+				file.clone(),
+				tile(None, x, y),
+				String::from("Duplicate spawn"),
+			));
+		}
+	}
+
+	problems
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::path::PathBuf;
+
+	fn class_named(name: &str) -> ClassEntry {
+		let mut class = ClassEntry::with_texture(PathBuf::new());
+		class.data.name = String::from(name);
+		class
+	}
+
+	fn messages(problems: &[Diagnostic]) -> Vec<&str> {
+		problems.iter().map(|p| p.message.as_str()).collect()
+	}
+
+	fn lint_units(map: &MapEditor, classes: &[ClassEntry]) -> Vec<Diagnostic> {
+		lint_map(map, classes, &[], &[], &Factions::new(), 16)
+	}
+
+	#[test]
+	fn valid_classes_have_no_problems() {
+		let classes = [class_named("Knight"), class_named("General")];
+		assert!(lint_classes(&classes, &[], &[], |_| true).is_empty());
+	}
+
+	#[test]
+	fn class_names_must_be_unique() {
+		let classes = [
+			class_named("Knight"),
+			class_named("Knight"),
+			class_named(""),
+		];
+		let problems = lint_classes(&classes, &[], &[], |_| true);
+		assert_eq!(
+			messages(&problems),
+			[
+				"More than one class is named \"Knight\"",
+				"Class has no name"
+			]
+		);
+		assert!(problems.iter().all(|p| p.severity == Severity::Error));
+		assert_eq!(
+			problems[0].location,
+			Some(ProblemLocation::Class(classes[1].uuid))
+		);
+	}
+
+	#[test]
+	fn units_outside_of_the_map_are_errors() {
+		let classes = [class_named("Knight")];
+		let mut map = MapEditor::with_size(String::from("test"), 2, 2);
+		let faction = Factions::new().default_faction().to_string();
+		map.units.push(Unit::new(1, 1, classes[0].uuid, &faction));
+		map.units.push(Unit::new(5, 0, classes[0].uuid, &faction));
+		let problems = lint_units(&map, &classes);
+		assert_eq!(messages(&problems), ["Unit is outside of the map"]);
+		assert_eq!(problems[0].severity, Severity::Error);
+	}
+
+	#[test]
+	fn units_with_missing_classes_are_errors() {
+		let mut map = MapEditor::with_size(String::from("test"), 2, 2);
+		let faction = Factions::new().default_faction().to_string();
+		let class = Uuid::new_v4();
+		map.units.push(Unit::new(0, 0, class, &faction));
+		let problems = lint_units(&map, &[class_named("Knight")]);
+		assert_eq!(
+			messages(&problems),
+			[format!("Unit refers to a missing class ({class})")]
+		);
+	}
+
+	#[test]
+	fn duplicate_spawns_are_warnings() {
+		let mut map = MapEditor::with_size(String::from("test"), 2, 2);
+		map.data.spawns = vec![(0, 1), (1, 1), (0, 1)];
+		let problems = lint_units(&map, &[]);
+		assert_eq!(messages(&problems), ["Duplicate spawn"]);
+		assert_eq!(problems[0].severity, Severity::Warning);
+		assert_eq!(
+			problems[0].location,
+			Some(ProblemLocation::Tile {
+				map: String::from("test"),
+				layer: None,
+				x: 0,
+				y: 1,
+			})
+		);
+	}
+}
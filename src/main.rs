@@ -2,20 +2,115 @@
 
 use fe_editor::*;
 use imgui::*;
+use imgui_glium_renderer::Texture;
+use std::collections::HashMap;
 use std::env;
 use std::error::Error;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::time::{Duration, Instant};
 use toml::*;
 
+const WINDOW_TITLE: &str = "Furry Emblem - Editor";
+
 const MAIN_MENU_HEIGHT: f32 = 22.0;
 const MAP_VIEWER_MARGIN: f32 = 32.0;
 const TILE_SELECTOR_MARGIN: f32 = 80.0;
+/// The width of the unit inspector, which is docked beside the tile selector.
+const UNIT_INSPECTOR_WIDTH: f32 = 280.0;
 const EDITOR_LIST_Y: f32 = MAIN_MENU_HEIGHT + 4.0;
 
 const CURSOR_PNG: &[u8] = include_bytes!("cursor.png");
 
+/// Where autosaves are written, relative to the project.
+/// The files inside mirror the layout of the project itself.
+const RECOVERY_DIR: &str = "recovery";
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+// The smallest map which fills the screen.
+const MIN_MAP_WIDTH: usize = 15;
+const MIN_MAP_HEIGHT: usize = 10;
+
+/// Something which would lose unsaved work, waiting to be confirmed.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PendingAction {
+	Quit,
+	/// Closing the map tab at this index.
+	CloseMap(usize),
+}
+
+/// The editor which receives undo and redo.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FocusedEditor {
+	Map,
+	Classes,
+	Terrain,
+	Items,
+	Characters,
+}
+
+/// The maps open in the map viewer, one per tab.
+struct MapTabs {
+	editors: Vec<MapEditor>,
+	active: usize,
+	/// A tab which should be brought to the front on the next frame.
+	select: Option<usize>,
+}
+
+impl MapTabs {
+	fn new() -> Self {
+		Self {
+			editors: Vec::new(),
+			active: 0,
+			select: None,
+		}
+	}
+
+	fn active(&self) -> Option<&MapEditor> {
+		self.editors.get(self.active)
+	}
+
+	fn active_mut(&mut self) -> Option<&mut MapEditor> {
+		self.editors.get_mut(self.active)
+	}
+
+	fn find(&self, name: &str) -> Option<usize> {
+		self.editors.iter().position(|m| m.data.name == name)
+	}
+
+	/// Adds a map in a new tab and switches to it.
+	/// If a map with the same name is already open, it's replaced if it has no unsaved changes.
+	/// Otherwise, the open map is switched to instead, and false is returned.
+	fn open(&mut self, editor: MapEditor) -> bool {
+		let (index, opened) = match self.find(&editor.data.name) {
+			Some(index) if !self.editors[index].save_state.is_saved() => (index, false),
+			Some(index) => {
+				self.editors[index] = editor;
+				(index, true)
+			}
+			None => {
+				self.editors.push(editor);
+				(self.editors.len() - 1, true)
+			}
+		};
+		self.active = index;
+		self.select = Some(index);
+		opened
+	}
+
+	fn close(&mut self, index: usize) {
+		self.editors.remove(index);
+		if self.active > index || self.active >= self.editors.len() {
+			self.active = self.active.saturating_sub(1);
+		}
+	}
+
+	fn any_unsaved(&self) -> bool {
+		self.editors.iter().any(|m| !m.save_state.is_saved())
+	}
+}
+
 struct NewMapPopup {
 	capsule: ModalCapsule,
 	width: usize,
@@ -27,20 +122,62 @@ impl NewMapPopup {
 	fn new() -> Self {
 		Self {
 			capsule: ModalCapsule::new(),
-			width: 15,
-			height: 10,
+			width: MIN_MAP_WIDTH,
+			height: MIN_MAP_HEIGHT,
 			path: String::new(),
 		}
 	}
 }
 
+struct MapPropertiesPopup {
+	capsule: ModalCapsule,
+	width: usize,
+	height: usize,
+	anchor: Anchor,
+	shift: [i32; 2],
+	fill: usize,
+}
+
+impl MapPropertiesPopup {
+	fn new() -> Self {
+		Self {
+			capsule: ModalCapsule::new(),
+			width: MIN_MAP_WIDTH,
+			height: MIN_MAP_HEIGHT,
+			anchor: Anchor { x: 0, y: 0 },
+			shift: [0, 0],
+			fill: 0,
+		}
+	}
+
+	/// Resets the popup's fields to match a map.
+	/// New tiles can't be left empty, since the bottom layer has to be filled,
+	/// so erasing fills them with the first tile instead.
+	fn open(&mut self, map: &MapData, fill: usize) {
+		self.capsule.open();
+		self.width = map.width;
+		self.height = map.height;
+		self.anchor = Anchor { x: 0, y: 0 };
+		self.shift = [0, 0];
+		self.fill = if fill == EMPTY_TILE { 0 } else { fill };
+	}
+}
+
 struct EditorConfig {
 	save_path: PathBuf,
+	tileset: TilesetLayout,
+	/// A command to run instead of opening the editor.
+	command: Option<cli::Command>,
 }
 
 impl EditorConfig {
 	fn open() -> Result<Self, Box<dyn Error>> {
-		let args: Vec<String> = env::args().collect();
+		let mut args: Vec<String> = env::args().collect();
+
+		let command = args.get(1).and_then(|arg| cli::Command::from_arg(arg));
+		if command.is_some() {
+			args.remove(1);
+		}
 
 		let config_path = if args.len() == 1 {
 			"fe-editor.toml"
@@ -48,13 +185,15 @@ impl EditorConfig {
 			&args[1]
 		} else {
 			Err(FeError::from(format!(
-				"Too many args. Usage: {} [config file]",
+				"Too many args. Usage: {} [check | convert] [config file]",
 				args[0]
 			)))?
 		};
 
 		let mut config = EditorConfig {
 			save_path: PathBuf::from("."),
+			tileset: TilesetLayout::new(),
+			command,
 		};
 
 		match fs::read_to_string(config_path) {
@@ -69,6 +208,17 @@ impl EditorConfig {
 								eprintln!("Failed to read project path: not a string");
 							}
 						}
+						// A bad layout would load the wrong tiles, so don't fall back to the default.
+						"tileset" => {
+							let Value::Table(table) = value else {
+								Err(FeError::from(format!(
+									"Failed to read {config_path}: tileset is not a table"
+								)))?
+							};
+							config.tileset = TilesetLayout::from_table(&table).map_err(|msg| {
+								FeError::from(format!("Failed to read tileset settings: {msg}"))
+							})?;
+						}
 						_ => {
 							eprintln!("Unrecognized key: {key}");
 						}
@@ -111,38 +261,406 @@ fn append_path(path: &PathBuf, s: &str) -> PathBuf {
 	[&*path.to_string_lossy(), s].iter().collect()
 }
 
-fn save(
-	path: PathBuf,
-	class_editor: &mut ClassEditor,
-	map_editor: &mut Option<MapEditor>,
-) -> Result<(), Box<dyn Error>> {
+fn load_tileset(
+	path: &PathBuf,
+	layout: &TilesetLayout,
+	gl_ctx: &impl Facade,
+	textures: &mut Textures<Texture>,
+) -> Result<TileAtlas, Box<dyn Error>> {
+	register_tileset(gl_ctx, textures, &image::open(path)?, layout)
+}
+
+/// Registers every image in an icons directory, such as `class-icons`.
+fn load_icons(
+	path: &PathBuf,
+	gl_ctx: &impl Facade,
+	textures: &mut Textures<Texture>,
+) -> HashMap<PathBuf, TextureId> {
+	let mut icons = HashMap::new();
+	if let Err(msg) = walk_directory(path, |entry| {
+		icons.insert(
+			entry.path(),
+			register_image(gl_ctx, textures, &image::open(entry.path())?)?,
+		);
+		Ok(())
+	}) {
+		eprintln!("Failed to load icons from {}: {msg}", path.display());
+	}
+	icons
+}
+
+fn save_map(path: &PathBuf, map_editor: &mut MapEditor) -> Result<(), Box<dyn Error>> {
+	let maps_path = append_path(path, "maps");
+	fs::create_dir_all(&maps_path)?;
+	let toml = map_editor.to_toml()?;
+	fs::write(
+		append_path(&maps_path, &format!("{}.toml", map_editor.data.name)),
+		toml,
+	)?;
+	map_editor.mark_saved();
+	discard_recovery(path, &format!("maps/{}.toml", map_editor.data.name));
+	Ok(())
+}
+
+/// Writes `toml` to `file` in the directory at `path`.
+/// Errors are prefixed with the file's name, so that several can be reported together.
+fn write_toml(
+	path: &PathBuf,
+	file: &str,
+	toml: Result<String, Box<dyn Error>>,
+) -> Result<(), String> {
+	toml.and_then(|toml| Ok(fs::write(append_path(path, file), toml)?))
+		.map_err(|msg| format!("{file}: {msg}"))
+}
+
+/// An editor which is saved to a single file in the project.
+trait ProjectFile {
+	/// The file's name, relative to the project.
+	fn file_name(&self) -> &'static str;
+	fn save_state(&mut self) -> &mut SaveState;
+	fn to_toml(&self) -> Result<String, Box<dyn Error>>;
+	fn mark_saved(&mut self);
+}
+
+impl ProjectFile for ClassEditor {
+	fn file_name(&self) -> &'static str {
+		"classes.toml"
+	}
+
+	fn save_state(&mut self) -> &mut SaveState {
+		&mut self.save_state
+	}
+
+	fn to_toml(&self) -> Result<String, Box<dyn Error>> {
+		self.to_toml()
+	}
+
+	fn mark_saved(&mut self) {
+		self.mark_saved();
+	}
+}
+
+impl ProjectFile for TerrainEditor {
+	fn file_name(&self) -> &'static str {
+		"terrain.toml"
+	}
+
+	fn save_state(&mut self) -> &mut SaveState {
+		&mut self.save_state
+	}
+
+	fn to_toml(&self) -> Result<String, Box<dyn Error>> {
+		self.to_toml()
+	}
+
+	fn mark_saved(&mut self) {
+		self.mark_saved();
+	}
+}
+
+impl ProjectFile for ItemEditor {
+	fn file_name(&self) -> &'static str {
+		"items.toml"
+	}
+
+	fn save_state(&mut self) -> &mut SaveState {
+		&mut self.save_state
+	}
+
+	fn to_toml(&self) -> Result<String, Box<dyn Error>> {
+		self.to_toml()
+	}
+
+	fn mark_saved(&mut self) {
+		self.mark_saved();
+	}
+}
+
+impl ProjectFile for CharacterEditor {
+	fn file_name(&self) -> &'static str {
+		"characters.toml"
+	}
+
+	fn save_state(&mut self) -> &mut SaveState {
+		&mut self.save_state
+	}
+
+	fn to_toml(&self) -> Result<String, Box<dyn Error>> {
+		self.to_toml()
+	}
+
+	fn mark_saved(&mut self) {
+		self.mark_saved();
+	}
+}
+
+/// Every editor in the project, borrowed so that they can be saved or restored together.
+struct Editors<'a> {
+	classes: &'a mut ClassEditor,
+	terrain: &'a mut TerrainEditor,
+	items: &'a mut ItemEditor,
+	characters: &'a mut CharacterEditor,
+	map_tabs: &'a mut MapTabs,
+}
+
+impl Editors<'_> {
+	/// The editors which are each saved to a single file, rather than one file per map.
+	fn files(&mut self) -> [&mut dyn ProjectFile; 4] {
+		[
+			&mut *self.classes,
+			&mut *self.terrain,
+			&mut *self.items,
+			&mut *self.characters,
+		]
+	}
+}
+
+/// Saves the classes, the terrain, the items, the characters, and every map with unsaved changes.
+/// Each is saved even if another can't be, and every error is reported together.
+fn save(path: PathBuf, mut editors: Editors) -> Result<(), Box<dyn Error>> {
 	fs::create_dir_all(&path)?;
+	let mut errors = Vec::new();
 
-	let toml = class_editor.to_toml()?;
-	fs::write(append_path(&path, "classes.toml"), toml)?;
-	class_editor.unsaved = false;
+	for file in editors.files() {
+		match write_toml(&path, file.file_name(), file.to_toml()) {
+			Ok(()) => {
+				file.mark_saved();
+				discard_recovery(&path, file.file_name());
+			}
+			Err(msg) => errors.push(msg),
+		}
+	}
+
+	for map_editor in &mut editors.map_tabs.editors {
+		if !map_editor.save_state.is_saved() {
+			if let Err(msg) = save_map(&path, map_editor) {
+				errors.push(format!("maps/{}.toml: {msg}", map_editor.data.name));
+			}
+		}
+	}
+
+	if !errors.is_empty() {
+		Err(FeError::from(errors.join("\n")))?;
+	}
+	Ok(())
+}
+
+/// Writes anything which has changed since it was last saved or autosaved to the recovery directory.
+/// Like `save`, a file which can't be written doesn't stop the others.
+fn autosave(path: &PathBuf, mut editors: Editors) -> Result<(), Box<dyn Error>> {
+	let recovery_path = append_path(path, RECOVERY_DIR);
+	fs::create_dir_all(&recovery_path)?;
+	let mut errors = Vec::new();
+
+	for file in editors.files() {
+		if !file.save_state().is_autosaved() {
+			match write_toml(&recovery_path, file.file_name(), file.to_toml()) {
+				Ok(()) => file.save_state().mark_autosaved(),
+				Err(msg) => errors.push(msg),
+			}
+		}
+	}
+
+	for map_editor in &mut editors.map_tabs.editors {
+		if !map_editor.save_state.is_autosaved() {
+			let file = format!("maps/{}.toml", map_editor.data.name);
+			if let Err(msg) = fs::create_dir_all(append_path(&recovery_path, "maps")) {
+				errors.push(format!("{file}: {msg}"));
+				continue;
+			}
+			match write_toml(&recovery_path, &file, map_editor.to_toml()) {
+				Ok(()) => map_editor.save_state.mark_autosaved(),
+				Err(msg) => errors.push(msg),
+			}
+		}
+	}
+
+	if !errors.is_empty() {
+		Err(FeError::from(errors.join("\n")))?;
+	}
+	Ok(())
+}
+
+/// Removes an autosaved file once the real one has been saved.
+fn discard_recovery(path: &PathBuf, file: &str) {
+	let recovery_path = append_path(path, RECOVERY_DIR);
+	// The file usually won't exist, which is fine.
+	let _ = fs::remove_file(append_path(&recovery_path, file));
+}
 
-	if let Some(map_editor) = map_editor {
-		let mut maps_path = PathBuf::new();
-		maps_path.push(&path);
-		maps_path.push("maps");
-		fs::create_dir_all(&maps_path)?;
-		maps_path.push(&map_editor.data.name);
-		maps_path.set_extension("toml");
-		let toml = map_editor.data.to_toml()?;
-		fs::write(maps_path, toml)?;
+/// Loads recovered files into the editors.
+/// They're marked as unsaved, so saving them replaces the files they were recovered from.
+fn restore(
+	path: &PathBuf,
+	recovery: &Recovery,
+	editors: Editors,
+	factions: &Factions,
+	tile_count: usize,
+) -> Result<(), Box<dyn Error>> {
+	let recovery_path = append_path(path, RECOVERY_DIR);
+
+	if recovery.classes {
+		*editors.classes = ClassEditor::open(
+			append_path(&recovery_path, "classes.toml"),
+			editors.classes.default_icon.clone(),
+		)?;
+		editors.classes.save_state.mark_unsaved();
+	}
+
+	if recovery.terrain {
+		*editors.terrain =
+			TerrainEditor::open(append_path(&recovery_path, "terrain.toml"), tile_count)?;
+		editors.terrain.save_state.mark_unsaved();
+	}
+
+	if recovery.items {
+		*editors.items = ItemEditor::open(append_path(&recovery_path, "items.toml"))?;
+		editors.items.save_state.mark_unsaved();
+	}
+
+	if recovery.characters {
+		*editors.characters =
+			CharacterEditor::open(append_path(&recovery_path, "characters.toml"))?;
+		editors.characters.save_state.mark_unsaved();
+	}
+
+	// Maps which were changed since the editor started are kept, along with their recovered files.
+	let mut skipped = Vec::new();
+	for name in &recovery.maps {
+		let mut editor = MapEditor::open(
+			append_path(&recovery_path, "maps"),
+			name.clone(),
+			&editors.classes.classes,
+			factions,
+		)?;
+		editor.save_state.mark_unsaved();
+		if !editors.map_tabs.open(editor) {
+			skipped.push(name.as_str());
+		}
+	}
+	if !skipped.is_empty() {
+		Err(FeError::from(format!(
+			"{} already had unsaved changes, so they were not restored",
+			skipped.join(", ")
+		)))?;
 	}
 
 	Ok(())
 }
 
+/// Identifies the state of every editor, so that problems are only found again once one changes.
+fn edit_revisions(
+	class_editor: &ClassEditor,
+	terrain_editor: &TerrainEditor,
+	item_editor: &ItemEditor,
+	character_editor: &CharacterEditor,
+	map_tabs: &MapTabs,
+) -> Vec<(String, usize, bool)> {
+	let mut revisions = vec![
+		(
+			String::from("classes"),
+			class_editor.history.revision(),
+			class_editor.save_state.is_saved(),
+		),
+		(
+			String::from("terrain"),
+			terrain_editor.history.revision(),
+			terrain_editor.save_state.is_saved(),
+		),
+		(
+			String::from("items"),
+			item_editor.history.revision(),
+			item_editor.save_state.is_saved(),
+		),
+		(
+			String::from("characters"),
+			character_editor.history.revision(),
+			character_editor.save_state.is_saved(),
+		),
+	];
+	revisions.extend(map_tabs.editors.iter().map(|m| {
+		(
+			format!("maps/{}", m.data.name),
+			m.history.revision(),
+			m.save_state.is_saved(),
+		)
+	}));
+	revisions
+}
+
+/// Autosaved files which are newer than their saved counterparts.
+struct Recovery {
+	classes: bool,
+	terrain: bool,
+	items: bool,
+	characters: bool,
+	/// The names of recovered maps.
+	maps: Vec<String>,
+}
+
+impl Recovery {
+	fn find(path: &PathBuf) -> Self {
+		let recovery_path = append_path(path, RECOVERY_DIR);
+		let is_newer = |file: &str| {
+			let modified = |path: PathBuf| fs::metadata(path).and_then(|m| m.modified()).ok();
+			match (
+				modified(append_path(&recovery_path, file)),
+				modified(append_path(path, file)),
+			) {
+				(Some(recovered), Some(saved)) => recovered > saved,
+				(Some(_), None) => true,
+				(None, _) => false,
+			}
+		};
+
+		let mut maps = Vec::new();
+		if let Ok(dir) = fs::read_dir(append_path(&recovery_path, "maps")) {
+			for entry in dir.filter_map(|e| e.ok()) {
+				// Only TOML files are maps, and their names can contain dots.
+				let file_name = entry.file_name().to_string_lossy().to_string();
+				let Some(name) = file_name.strip_suffix(".toml") else {
+					continue;
+				};
+				let name = name.to_string();
+				if is_newer(&format!("maps/{name}.toml")) {
+					maps.push(name);
+				}
+			}
+		}
+
+		Self {
+			classes: is_newer("classes.toml"),
+			terrain: is_newer("terrain.toml"),
+			items: is_newer("items.toml"),
+			characters: is_newer("characters.toml"),
+			maps,
+		}
+	}
+
+	fn is_empty(&self) -> bool {
+		!self.classes && !self.terrain && !self.items && !self.characters && self.maps.is_empty()
+	}
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
-	let mut system = support::init("Furry Emblem - Editor");
 	let config = EditorConfig::open()?;
+	if let Some(command) = config.command {
+		if !cli::run(command, &config.save_path, &config.tileset) {
+			exit(1);
+		}
+		return Ok(());
+	}
+
+	let mut system = support::init(WINDOW_TITLE);
 	let maps_path: PathBuf = append_path(&config.save_path, "maps");
 	let unit_icons_path: PathBuf = append_path(&config.save_path, "class-icons");
+	let item_icons_path: PathBuf = append_path(&config.save_path, "item-icons");
+	let portraits_path: PathBuf = append_path(&config.save_path, "portraits");
 
 	let mut selected_tile = 0;
+	let mut tool = MapTool::Brush;
+	// Regions are also copied to the system clipboard, but this is kept in case it's unavailable.
+	let mut map_clipboard: Option<MapRegion> = None;
 
 	let cursor_tile = register_image(
 		system.display.get_context(),
@@ -152,53 +670,107 @@ fn main() -> Result<(), Box<dyn Error>> {
 	)
 	.unwrap();
 
-	let texture_atlas = register_tileset(
+	let missing_textures =
+		MissingTextures::new(system.display.get_context(), system.renderer.textures())?;
+
+	let tileset_path = append_path(&config.save_path, "tileset.png");
+	let mut texture_atlas = match load_tileset(
+		&tileset_path,
+		&config.tileset,
 		system.display.get_context(),
 		system.renderer.textures(),
-		&image::open(append_path(&config.save_path, "tileset.png"))?,
-	)
-	.unwrap();
+	) {
+		Ok(texture_atlas) => texture_atlas,
+		Err(msg) => {
+			eprintln!("Failed to load tileset: {msg}");
+			exit(1);
+		}
+	};
 
 	// In the future, class/unit icons should be loaded from some config file.
 	// Classes can be serialized in unit data as their names, since this is how users will identify them.
-	let mut default_class_icon = None;
-	let mut unit_icons = ClassIcons::new();
+	let mut unit_icons = load_icons(
+		&unit_icons_path,
+		system.display.get_context(),
+		system.renderer.textures(),
+	);
 
-	if let Err(msg) = walk_directory(&unit_icons_path, |entry| {
-		// Set the default class to whatever we find first.
-		default_class_icon = Some(entry.path());
-		unit_icons.insert(
-			entry.path(),
-			register_image(
-				system.display.get_context(),
-				system.renderer.textures(),
-				&image::open(entry.path())?,
-			)?,
-		);
-		Ok(())
-	}) {
-		eprintln!("Failed to load unit icons: {msg}");
-	}
+	let mut item_icons = load_icons(
+		&item_icons_path,
+		system.display.get_context(),
+		system.renderer.textures(),
+	);
 
-	if unit_icons.len() == 0 {
+	let mut portraits = load_icons(
+		&portraits_path,
+		system.display.get_context(),
+		system.renderer.textures(),
+	);
+
+	// Set the default class to whatever comes first.
+	let Some(default_class_icon) = unit_icons.keys().min().cloned() else {
 		eprintln!("No unit icons are loaded. Exiting.");
 		exit(1);
-	}
+	};
 
 	// Editors
-	let mut class_editor = ClassEditor::open(
-		append_path(&config.save_path, "classes.toml"),
-		// This is safe to unwrap.
-		default_class_icon.unwrap(),
+	let classes_path = append_path(&config.save_path, "classes.toml");
+	let mut class_editor = ClassEditor::open(&classes_path, default_class_icon)?;
+	let mut terrain_editor = TerrainEditor::open(
+		append_path(&config.save_path, "terrain.toml"),
+		texture_atlas.len(),
 	)?;
-	let mut map_editor: Option<MapEditor> = None;
+	let mut item_editor = ItemEditor::open(append_path(&config.save_path, "items.toml"))?;
+	let mut character_editor =
+		CharacterEditor::open(append_path(&config.save_path, "characters.toml"))?;
+	// Factions only change how units are drawn and checked, so a bad file shouldn't stop the editor.
+	let (factions, factions_error) =
+		match Factions::open(append_path(&config.save_path, "factions.toml")) {
+			Ok(factions) => (factions, None),
+			Err(msg) => (Factions::new(), Some(msg)),
+		};
+	let mut map_tabs = MapTabs::new();
+	// Undo and redo are sent to whichever editor was focused last.
+	let mut focused_editor = FocusedEditor::Map;
 
 	// Popups
 	let mut new_map_popup = NewMapPopup::new();
+	let mut map_properties_popup = MapPropertiesPopup::new();
 	let mut warning_message = String::new();
-	let mut level_name = String::new();
+	let mut startup_warning = factions_error
+		.map(|msg| format!("Failed to load factions.toml: {msg}\nUsing the default factions."));
+	if let Some(msg) = &startup_warning {
+		eprintln!("{msg}");
+	}
+
+	// Offer to restore anything which was autosaved but never saved, such as after a crash.
+	let recovery = Recovery::find(&config.save_path);
+	let mut recovery_popup = ModalCapsule::new();
+	if !recovery.is_empty() {
+		recovery_popup.open();
+	}
+	let mut last_autosave = Instant::now();
+	// Shown beside anything unsaved, so that nobody relies on a backup which isn't being made.
+	let mut autosave_error: Option<String> = None;
+	let mut window_title = String::from(WINDOW_TITLE);
+	let mut pending_action = None;
+	let mut show_draw_stats = false;
+	// Checking every open map is too slow to do every frame,
+	// so problems are only found again once something is edited, saved or reloaded.
+	let mut problems = Vec::new();
+	let mut problems_revisions = Vec::new();
+	let mut problems_stale = true;
+
+	// Reload art as soon as it's changed, so that artists can see their work without restarting.
+	// Only classes.toml is also edited here, so it's the only file which can conflict with unsaved changes.
+	let mut file_watcher = FileWatcher::new();
+	file_watcher.watch(&tileset_path);
+	file_watcher.watch(&unit_icons_path);
+	file_watcher.watch(&item_icons_path);
+	file_watcher.watch(&portraits_path);
+	file_watcher.watch(&classes_path);
 
-	system.main_loop(move |_, ui| {
+	system.main_loop(move |run, ui, window, display, textures| {
 		let display_size = ui.io().display_size;
 
 		let (ctrl, ctrl_str) = if ui.io().config_mac_os_behaviors {
@@ -208,24 +780,94 @@ fn main() -> Result<(), Box<dyn Error>> {
 		};
 		let mut warning_popup = ModalCapsule::new();
 		let mut open_map_popup = ModalCapsule::new();
+		let mut unsaved_popup = ModalCapsule::new();
+		let mut classes_changed_popup = ModalCapsule::new();
+		let mut reload_classes = false;
 		new_map_popup.capsule.reset();
+		map_properties_popup.capsule.reset();
 		// for the sake of not repeating save code:
 		let mut manual_save = false;
+		let mut manual_undo = false;
+		let mut manual_redo = false;
+		let mut manual_cut = false;
+		let mut manual_copy = false;
+		let mut manual_paste = false;
 
 		ui.main_menu_bar(|| {
 			ui.menu("File", || {
 				if ui.menu_item("New Map") {
 					new_map_popup.capsule.open();
-					level_name = String::new();
 				}
 				if ui.menu_item("Open Map") {
 					open_map_popup.open();
-					level_name = String::new();
 				}
-				if ui.menu_item(&format!("Save ({ctrl_str} + S)")) {
+				if ui.menu_item(format!("Save ({ctrl_str} + S)")) {
 					manual_save = true;
 				}
 			});
+			ui.menu("Edit", || {
+				if ui.menu_item(format!("Undo ({ctrl_str} + Z)")) {
+					manual_undo = true;
+				}
+				if ui.menu_item(format!("Redo ({ctrl_str} + Shift + Z)")) {
+					manual_redo = true;
+				}
+				ui.separator();
+				if ui.menu_item(format!("Cut ({ctrl_str} + X)")) {
+					manual_cut = true;
+				}
+				if ui.menu_item(format!("Copy ({ctrl_str} + C)")) {
+					manual_copy = true;
+				}
+				if ui.menu_item(format!("Paste ({ctrl_str} + V)")) {
+					manual_paste = true;
+				}
+				ui.separator();
+				if ui.menu_item("Fill Selection") {
+					if let Some(map_editor) = map_tabs.active_mut() {
+						if let Some(selection) = map_editor.selection {
+							map_editor.fill_rect(selection, selected_tile);
+						}
+					}
+				}
+				if ui.menu_item("Deselect (Esc)") {
+					if let Some(map_editor) = map_tabs.active_mut() {
+						map_editor.paste = None;
+						map_editor.selection = None;
+					}
+				}
+			});
+			ui.menu("Map", || {
+				if ui
+					.menu_item_config("Properties")
+					.enabled(map_tabs.active().is_some())
+					.build()
+				{
+					if let Some(map_editor) = map_tabs.active() {
+						map_properties_popup.open(&map_editor.data, selected_tile);
+					}
+				}
+				ui.separator();
+				if ui
+					.menu_item_config("Show Draw Statistics")
+					.selected(show_draw_stats)
+					.build()
+				{
+					show_draw_stats = !show_draw_stats;
+				}
+			});
+			ui.menu("Tools", || {
+				for i in MapTool::ALL {
+					if ui
+						.menu_item_config(i.name())
+						.shortcut(i.shortcut())
+						.selected(tool == i)
+						.build()
+					{
+						tool = i;
+					}
+				}
+			});
 			ui.menu("Info", || {
 				ui.text("Furry Emblem Editor");
 				ui.text("By Evie M.");
@@ -236,15 +878,144 @@ fn main() -> Result<(), Box<dyn Error>> {
 			&ui,
 			(MAP_VIEWER_MARGIN + 200.0 * 0.0, EDITOR_LIST_Y),
 			&unit_icons,
+			&missing_textures,
+			&terrain_editor.data.movement_classes,
+			&item_editor.items,
+		);
+		terrain_editor.draw(
+			&ui,
+			(MAP_VIEWER_MARGIN + 200.0 * 2.0, EDITOR_LIST_Y),
+			&texture_atlas,
+		);
+		item_editor.draw(
+			&ui,
+			(MAP_VIEWER_MARGIN + 200.0 * 3.0, EDITOR_LIST_Y),
+			&item_icons,
+			&missing_textures,
 		);
+		character_editor.draw(
+			&ui,
+			(MAP_VIEWER_MARGIN + 200.0 * 4.0, EDITOR_LIST_Y),
+			&portraits,
+			&missing_textures,
+			&class_editor.classes,
+		);
+		if class_editor.focused {
+			focused_editor = FocusedEditor::Classes;
+		}
+		if terrain_editor.focused {
+			focused_editor = FocusedEditor::Terrain;
+		}
+		if item_editor.focused {
+			focused_editor = FocusedEditor::Items;
+		}
+		if character_editor.focused {
+			focused_editor = FocusedEditor::Characters;
+		}
+
+		let mut jump_to = None;
+		ui.window("Problems")
+			.position(
+				[MAP_VIEWER_MARGIN + 200.0 * 5.0, EDITOR_LIST_Y],
+				Condition::FirstUseEver,
+			)
+			.size([400.0, 200.0], Condition::FirstUseEver)
+			.focus_on_appearing(false)
+			.collapsed(true, Condition::FirstUseEver)
+			.build(|| {
+				let revisions = edit_revisions(
+					&class_editor,
+					&terrain_editor,
+					&item_editor,
+					&character_editor,
+					&map_tabs,
+				);
+				if problems_stale || revisions != problems_revisions {
+					problems_stale = false;
+					problems_revisions = revisions;
+					// Only open maps are checked.
+					problems = lint_classes(
+						&class_editor.classes,
+						&item_editor.items,
+						&terrain_editor.data.movement_classes,
+						|path| unit_icons.contains_key(path),
+					);
+					problems.extend(lint_items(&item_editor.items, |path| {
+						item_icons.contains_key(path)
+					}));
+					problems.extend(lint_characters(
+						&character_editor.characters,
+						&class_editor.classes,
+						|path| portraits.contains_key(path),
+					));
+					for map_editor in &map_tabs.editors {
+						problems.extend(lint_map(
+							map_editor,
+							&class_editor.classes,
+							&item_editor.items,
+							&character_editor.characters,
+							&factions,
+							texture_atlas.len(),
+						));
+					}
+				}
+
+				let missing = missing_textures.occurrences();
+				if problems.is_empty() && missing.is_empty() {
+					ui.text_disabled("No problems found.");
+				}
+				for (i, problem) in problems.iter().enumerate() {
+					let _id = ui.push_id_usize(i);
+					let color = match problem.severity {
+						Severity::Error => [1.0, 0.4, 0.4, 1.0],
+						Severity::Warning => [1.0, 0.8, 0.2, 1.0],
+					};
+					let _color = ui.push_style_color(StyleColor::Text, color);
+					if ui.selectable(problem.to_string()) {
+						jump_to = problem.location.clone();
+					}
+				}
+
+				if !missing.is_empty() {
+					ui.separator();
+					ui.text("Missing textures, drawn as placeholders:");
+					for texture in &missing {
+						ui.bullet_text(texture.to_string());
+					}
+				}
+			});
+
+		// The unit inspector is docked to the right of the map, so the map shrinks while it is open.
+		let map_viewer_width = if map_tabs.active().is_some_and(|map| map.inspected.is_some()) {
+			display_size[0] - TILE_SELECTOR_MARGIN - UNIT_INSPECTOR_WIDTH
+		} else {
+			display_size[0] - TILE_SELECTOR_MARGIN
+		};
+
+		match jump_to {
+			Some(ProblemLocation::Tile { map, layer, x, y }) => {
+				if let Some(index) = map_tabs.find(&map) {
+					map_tabs.active = index;
+					map_tabs.select = Some(index);
+					map_tabs.editors[index].reveal(
+						layer,
+						x,
+						y,
+						[map_viewer_width, display_size[1] - MAIN_MENU_HEIGHT],
+					);
+				}
+			}
+			Some(ProblemLocation::Class(uuid)) => class_editor.reveal(uuid),
+			Some(ProblemLocation::Item(uuid)) => item_editor.reveal(uuid),
+			Some(ProblemLocation::Character(uuid)) => character_editor.reveal(uuid),
+			None => {}
+		}
 
-		if let Some(mut map_editor) = map_editor.as_mut() {
+		let mut close_tab = None;
+		if !map_tabs.editors.is_empty() {
 			ui.window("Map Editor")
 				.size(
-					[
-						display_size[0] - TILE_SELECTOR_MARGIN,
-						display_size[0] - MAIN_MENU_HEIGHT,
-					],
+					[map_viewer_width, display_size[0] - MAIN_MENU_HEIGHT],
 					Condition::Always,
 				)
 				.position([0.0, MAIN_MENU_HEIGHT], Condition::Always)
@@ -252,17 +1023,87 @@ fn main() -> Result<(), Box<dyn Error>> {
 				.bring_to_front_on_focus(false)
 				.focus_on_appearing(false)
 				.no_decoration()
+				.unsaved_document(map_tabs.any_unsaved())
 				.build(|| {
-					ui.tilemap(
-						&mut map_editor,
-						&texture_atlas,
-						&class_editor.classes,
-						&unit_icons,
-						cursor_tile,
-						selected_tile,
-					)
+					TabBar::new("##maps").build(ui, || {
+						for (i, map_editor) in map_tabs.editors.iter_mut().enumerate() {
+							let mut flags = TabItemFlags::empty();
+							if !map_editor.save_state.is_saved() {
+								flags |= TabItemFlags::UNSAVED_DOCUMENT;
+							}
+							if map_tabs.select == Some(i) {
+								flags |= TabItemFlags::SET_SELECTED;
+							}
+							let mut open = true;
+							TabItem::new(map_editor.data.name.clone())
+								.opened(&mut open)
+								.flags(flags)
+								.build(ui, || {
+									map_tabs.active = i;
+									// Give the map its own window so that it's drawn below the tabs.
+									ui.child_window("##map")
+										.scroll_bar(false)
+										.scrollable(false)
+										.build(|| {
+											if ui.is_window_focused() {
+												focused_editor = FocusedEditor::Map;
+											}
+											let stats = ui.tilemap(
+												map_editor,
+												&texture_atlas,
+												ProjectContext {
+													classes: &class_editor.classes,
+													characters: &character_editor.characters,
+													class_icons: &unit_icons,
+													factions: &factions,
+													missing: &missing_textures,
+												},
+												cursor_tile,
+												selected_tile,
+												tool,
+											);
+
+											if show_draw_stats {
+												ui.set_cursor_pos([8.0, 28.0]);
+												ui.text(format!(
+													"Draw commands: {}\nTiles: {}\nUnits: {}\nSpawns: {}",
+													stats.commands,
+													stats.tiles,
+													stats.units,
+													stats.spawns
+												));
+											}
+
+											// Note whether the map has at least been autosaved.
+											if !map_editor.save_state.is_saved() {
+												ui.set_cursor_pos([8.0, 8.0]);
+												if let Some(msg) = &autosave_error {
+													ui.text_colored(
+														[1.0, 0.4, 0.4, 1.0],
+														"(autosave failed)",
+													);
+													ui.hover_tooltip(msg);
+												} else if map_editor.save_state.is_autosaved() {
+													ui.text_disabled("(autosaved)");
+												} else {
+													ui.text_colored(
+														[1.0, 0.8, 0.2, 1.0],
+														"(unsaved)",
+													);
+												}
+											}
+										});
+								});
+							if !open {
+								close_tab = Some(i);
+							}
+						}
+					});
+					map_tabs.select = None;
 				});
+		}
 
+		if let Some(map_editor) = map_tabs.active_mut() {
 			ui.window("Tile Selector")
 				.size(
 					[TILE_SELECTOR_MARGIN, display_size[0] - MAIN_MENU_HEIGHT],
@@ -277,15 +1118,203 @@ fn main() -> Result<(), Box<dyn Error>> {
 				.focus_on_appearing(false)
 				.no_decoration()
 				.build(|| {
-					selected_tile = ui.tile_selector(&texture_atlas, selected_tile, cursor_tile);
+					let allow_empty = map_editor.active_layer != 0;
+					selected_tile =
+						ui.tile_selector(&texture_atlas, selected_tile, cursor_tile, allow_empty);
 				});
+
+			ui.window("Layers")
+				.position(
+					[MAP_VIEWER_MARGIN + 200.0 * 1.0, EDITOR_LIST_Y],
+					Condition::FirstUseEver,
+				)
+				.size([200.0, 400.0], Condition::FirstUseEver)
+				.focus_on_appearing(false)
+				.collapsed(true, Condition::FirstUseEver)
+				.build(|| {
+					if ui.is_window_focused() {
+						focused_editor = FocusedEditor::Map;
+					}
+					ui.layer_list(map_editor);
+				});
+
+			if map_editor.inspected.is_some() {
+				let mut open = true;
+				ui.window("Unit Inspector")
+					.size(
+						[UNIT_INSPECTOR_WIDTH, display_size[1] - MAIN_MENU_HEIGHT],
+						Condition::Always,
+					)
+					.position(
+						[
+							display_size[0] - TILE_SELECTOR_MARGIN - UNIT_INSPECTOR_WIDTH,
+							MAIN_MENU_HEIGHT,
+						],
+						Condition::Always,
+					)
+					.opened(&mut open)
+					.movable(false)
+					.resizable(false)
+					.collapsible(false)
+					.focus_on_appearing(false)
+					.build(|| {
+						if ui.is_window_focused() {
+							focused_editor = FocusedEditor::Map;
+						}
+						ui.unit_inspector(
+							map_editor,
+							&class_editor.classes,
+							&character_editor.characters,
+							&item_editor.items,
+						);
+					});
+				if !open {
+					map_editor.inspected = None;
+				}
+			}
+
+			// The ground can't be erased.
+			if map_editor.active_layer == 0 && selected_tile == EMPTY_TILE {
+				selected_tile = 0;
+			}
 		}
 
-		// End-of-frame cleanup
-		class_editor.classes.retain(|i| i.is_open);
+		if let Some(index) = close_tab {
+			if map_tabs.editors[index].save_state.is_saved() {
+				map_tabs.close(index);
+			} else {
+				pending_action = Some(PendingAction::CloseMap(index));
+			}
+		}
+
+		// Text fields handle their own undo and clipboard.
+		if ctrl && !ui.io().want_text_input {
+			if ui.is_key_pressed(Key::Z) {
+				if ui.io().key_shift {
+					manual_redo = true;
+				} else {
+					manual_undo = true;
+				}
+			}
+			manual_cut |= ui.is_key_pressed(Key::X);
+			manual_copy |= ui.is_key_pressed(Key::C);
+			manual_paste |= ui.is_key_pressed(Key::V);
+		}
+
+		if let Some(map_editor) = map_tabs.active_mut() {
+			if manual_cut || manual_copy {
+				if let Some(selection) = map_editor.selection {
+					let region = map_editor.copy_region(selection);
+					match region.to_toml() {
+						Ok(toml) => ui.set_clipboard_text(toml),
+						Err(msg) => eprintln!("Failed to copy region to clipboard: {msg}"),
+					}
+					map_clipboard = Some(region);
+					// Cutting leaves the selected tile behind.
+					if manual_cut {
+						map_editor.clear_region(selection, selected_tile);
+					}
+				}
+			}
+
+			if manual_paste {
+				// Prefer the system clipboard so that regions can be pasted between editors.
+				let region = ui
+					.clipboard_text()
+					.and_then(|text| MapRegion::from_toml(&text, &factions).ok())
+					.or_else(|| map_clipboard.clone());
+				if let Some(mut region) = region {
+					// Regions from other projects may refer to classes or tiles which don't exist here.
+					region.units.retain(|u| class_editor.get(u.class).is_some());
+					region.layers.iter_mut().flatten().for_each(|t| {
+						if *t >= texture_atlas.len() && *t != EMPTY_TILE {
+							*t = 0;
+						}
+					});
+					map_editor.paste = Some(region);
+				}
+			}
+		}
+
+		// Tools only apply to the map, so other editors keep these keys to themselves.
+		if !ctrl && !ui.io().want_text_input && focused_editor == FocusedEditor::Map {
+			if ui.is_key_pressed(Key::B) {
+				tool = MapTool::Brush;
+			}
+			if ui.is_key_pressed(Key::R) {
+				tool = MapTool::Rectangle;
+			}
+			if ui.is_key_pressed(Key::G) {
+				tool = MapTool::Bucket;
+			}
+			if ui.is_key_pressed(Key::M) {
+				tool = MapTool::Select;
+			}
+			if ui.is_key_pressed(Key::Escape) {
+				if let Some(map_editor) = map_tabs.active_mut() {
+					if map_editor.paste.take().is_none() {
+						map_editor.selection = None;
+					}
+				}
+			}
+		}
+
+		match focused_editor {
+			FocusedEditor::Map => {
+				if let Some(map_editor) = map_tabs.active_mut() {
+					if manual_undo {
+						map_editor.undo();
+					}
+					if manual_redo {
+						map_editor.redo();
+					}
+				}
+			}
+			FocusedEditor::Classes => {
+				if manual_undo {
+					class_editor.undo();
+				}
+				if manual_redo {
+					class_editor.redo();
+				}
+			}
+			FocusedEditor::Terrain => {
+				if manual_undo {
+					terrain_editor.undo();
+				}
+				if manual_redo {
+					terrain_editor.redo();
+				}
+			}
+			FocusedEditor::Items => {
+				if manual_undo {
+					item_editor.undo();
+				}
+				if manual_redo {
+					item_editor.redo();
+				}
+			}
+			FocusedEditor::Characters => {
+				if manual_undo {
+					character_editor.undo();
+				}
+				if manual_redo {
+					character_editor.redo();
+				}
+			}
+		}
 
 		if manual_save || ctrl && ui.is_key_pressed(Key::S) {
-			match save(config.save_path.clone(), &mut class_editor, &mut map_editor) {
+			match save(
+				config.save_path.clone(),
+				Editors {
+					classes: &mut class_editor,
+					terrain: &mut terrain_editor,
+					items: &mut item_editor,
+					characters: &mut character_editor,
+					map_tabs: &mut map_tabs,
+				},
+			) {
 				Ok(_) => eprintln!("Saved"),
 				Err(err) => {
 					warning_message = format!("Save failed: {err}");
@@ -295,6 +1324,133 @@ fn main() -> Result<(), Box<dyn Error>> {
 			}
 		}
 
+		// The window was closed, but unsaved work has to be checked for first.
+		if !*run {
+			*run = true;
+			pending_action = Some(PendingAction::Quit);
+		}
+
+		// Quitting loses everything, but closing a tab only loses that map.
+		let unsaved_maps: Vec<usize> = match pending_action {
+			Some(PendingAction::Quit) => (0..map_tabs.editors.len())
+				.filter(|&i| !map_tabs.editors[i].save_state.is_saved())
+				.collect(),
+			Some(PendingAction::CloseMap(index)) => vec![index],
+			None => Vec::new(),
+		};
+		let classes_unsaved = !class_editor.save_state.is_saved();
+		let terrain_unsaved = !terrain_editor.save_state.is_saved();
+		let items_unsaved = !item_editor.save_state.is_saved();
+		let characters_unsaved = !character_editor.save_state.is_saved();
+		let mut proceed = None;
+		if let Some(action) = pending_action {
+			let unsaved = match action {
+				PendingAction::Quit => {
+					!unsaved_maps.is_empty()
+						|| classes_unsaved || terrain_unsaved
+						|| items_unsaved || characters_unsaved
+				}
+				PendingAction::CloseMap(_) => true,
+			};
+			if unsaved {
+				unsaved_popup.open();
+			} else {
+				proceed = pending_action.take();
+			}
+		}
+
+		unsaved_popup.build(&ui, "Unsaved Changes", || {
+			ui.text("The following have unsaved changes:");
+			for &i in &unsaved_maps {
+				ui.bullet_text(format!("Map: {}", map_tabs.editors[i].data.name));
+			}
+			if pending_action == Some(PendingAction::Quit) {
+				if classes_unsaved {
+					ui.bullet_text("Classes");
+				}
+				if terrain_unsaved {
+					ui.bullet_text("Terrain");
+				}
+				if items_unsaved {
+					ui.bullet_text("Items");
+				}
+				if characters_unsaved {
+					ui.bullet_text("Characters");
+				}
+			}
+
+			if ui.button("Save") {
+				let result = match pending_action {
+					Some(PendingAction::CloseMap(index)) => {
+						save_map(&config.save_path, &mut map_tabs.editors[index])
+					}
+					_ => save(
+						config.save_path.clone(),
+						Editors {
+							classes: &mut class_editor,
+							terrain: &mut terrain_editor,
+							items: &mut item_editor,
+							characters: &mut character_editor,
+							map_tabs: &mut map_tabs,
+						},
+					),
+				};
+				match result {
+					Ok(_) => proceed = pending_action.take(),
+					Err(err) => {
+						pending_action = None;
+						warning_message = format!("Save failed: {err}");
+						eprintln!("{warning_message}");
+						warning_popup.open();
+					}
+				}
+				ui.close_current_popup();
+			}
+			ui.same_line();
+			if ui.button("Discard") {
+				// Otherwise the discarded changes would be offered for recovery on the next startup.
+				match pending_action {
+					Some(PendingAction::CloseMap(index)) => {
+						let name = &map_tabs.editors[index].data.name;
+						discard_recovery(&config.save_path, &format!("maps/{name}.toml"));
+					}
+					_ => {
+						let mut editors = Editors {
+							classes: &mut class_editor,
+							terrain: &mut terrain_editor,
+							items: &mut item_editor,
+							characters: &mut character_editor,
+							map_tabs: &mut map_tabs,
+						};
+						for file in editors.files() {
+							if !file.save_state().is_saved() {
+								discard_recovery(&config.save_path, file.file_name());
+							}
+						}
+						for map_editor in &editors.map_tabs.editors {
+							if !map_editor.save_state.is_saved() {
+								let name = &map_editor.data.name;
+								discard_recovery(&config.save_path, &format!("maps/{name}.toml"));
+							}
+						}
+					}
+				}
+				proceed = pending_action.take();
+				ui.close_current_popup();
+			}
+			ui.same_line();
+			if ui.button("Cancel") {
+				pending_action = None;
+				ui.close_current_popup();
+			}
+		});
+
+		match proceed {
+			Some(PendingAction::Quit) => *run = false,
+			Some(PendingAction::CloseMap(index)) => map_tabs.close(index),
+			None => {}
+		}
+
 		new_map_popup.capsule.build(&ui, "New Map", || {
 			ui.dummy([300.0, 0.0]);
 			ui.input_text("##path", &mut new_map_popup.path)
@@ -311,8 +1467,8 @@ fn main() -> Result<(), Box<dyn Error>> {
 				.step(1)
 				.build();
 
-			new_map_popup.width = new_map_popup.width.max(15);
-			new_map_popup.height = new_map_popup.height.max(10);
+			new_map_popup.width = new_map_popup.width.max(MIN_MAP_WIDTH);
+			new_map_popup.height = new_map_popup.height.max(MIN_MAP_HEIGHT);
 
 			if ui.button("Cancel") {
 				ui.close_current_popup();
@@ -321,9 +1477,12 @@ fn main() -> Result<(), Box<dyn Error>> {
 			if new_map_popup.path.len() == 0 {
 				ui.button("Create");
 				ui.hover_tooltip("Level must have a name");
+			} else if map_tabs.find(&new_map_popup.path).is_some() {
+				ui.button("Create");
+				ui.hover_tooltip("A level with this name is already open");
 			} else {
 				if ui.button("Create") {
-					map_editor = Some(MapEditor::with_size(
+					map_tabs.open(MapEditor::with_size(
 						new_map_popup.path.clone(),
 						new_map_popup.width,
 						new_map_popup.height,
@@ -333,18 +1492,129 @@ fn main() -> Result<(), Box<dyn Error>> {
 			}
 		});
 
+		map_properties_popup
+			.capsule
+			.build(&ui, "Map Properties", || {
+				let Some(map_editor) = map_tabs.active_mut() else {
+					ui.close_current_popup();
+					return;
+				};
+
+				ui.dummy([300.0, 0.0]);
+				ui.text("Size:");
+				ui.input_scalar("Width", &mut map_properties_popup.width)
+					.step(1)
+					.build();
+				ui.input_scalar("Height", &mut map_properties_popup.height)
+					.step(1)
+					.build();
+				map_properties_popup.width = map_properties_popup.width.max(MIN_MAP_WIDTH);
+				map_properties_popup.height = map_properties_popup.height.max(MIN_MAP_HEIGHT);
+
+				ui.text("Anchor:");
+				for y in 0..3 {
+					for x in 0..3 {
+						if x != 0 {
+							ui.same_line();
+						}
+						let anchor = Anchor { x, y };
+						if ui.radio_button_bool(
+							format!("##anchor{x}{y}"),
+							map_properties_popup.anchor == anchor,
+						) {
+							map_properties_popup.anchor = anchor;
+						}
+					}
+				}
+
+				ui.text("Shift contents:");
+				ui.input_scalar("X", &mut map_properties_popup.shift[0])
+					.step(1)
+					.build();
+				ui.input_scalar("Y", &mut map_properties_popup.shift[1])
+					.step(1)
+					.build();
+
+				ui.text("Fill new tiles with:");
+				let last_tile = texture_atlas.len().saturating_sub(1);
+				map_properties_popup.fill = map_properties_popup.fill.min(last_tile);
+				missing_textures
+					.tile(&texture_atlas, map_properties_popup.fill)
+					.image(ui, [32.0; 2]);
+				ui.same_line();
+				ui.input_scalar("##fill", &mut map_properties_popup.fill)
+					.step(1)
+					.build();
+				map_properties_popup.fill = map_properties_popup.fill.min(last_tile);
+
+				let size = (map_properties_popup.width, map_properties_popup.height);
+				let anchor_offset = map_properties_popup
+					.anchor
+					.offset((map_editor.data.width, map_editor.data.height), size);
+				let offset = (
+					anchor_offset.0 + map_properties_popup.shift[0] as isize,
+					anchor_offset.1 + map_properties_popup.shift[1] as isize,
+				);
+				let (lost_units, lost_spawns) = map_editor.lost_contents(size.0, size.1, offset);
+
+				if !lost_units.is_empty() || !lost_spawns.is_empty() {
+					ui.separator();
+					ui.text_colored([1.0, 0.8, 0.2, 1.0], "The following will be removed:");
+					for unit in &lost_units {
+						let class = match class_editor.get(unit.class) {
+							Some(class) => &class.data.name,
+							None => "Missing class",
+						};
+						let name = unit.display_name(&character_editor.characters);
+						if name.is_empty() {
+							ui.text(format!("{class} at ({}, {})", unit.x, unit.y));
+						} else {
+							ui.text(format!("{name} ({class}) at ({}, {})", unit.x, unit.y));
+						}
+					}
+					for spawn in &lost_spawns {
+						ui.text(format!("Spawn at ({}, {})", spawn.0, spawn.1));
+					}
+				}
+
+				ui.separator();
+				if ui.button("Cancel") {
+					ui.close_current_popup();
+				}
+				ui.same_line();
+				if ui.button("Apply") {
+					let region =
+						map_editor.transformed(size.0, size.1, offset, map_properties_popup.fill);
+					map_editor.replace_contents(region);
+					ui.close_current_popup();
+				}
+			});
+
 		open_map_popup.build(&ui, "Open Map", || {
 			ui.dummy([300.0, 0.0]);
 			ui.text("Select a level:");
 
 			if let Err(msg) = walk_directory(&maps_path, |entry| {
+				// Only TOML files are maps, and their names can contain dots.
+				let file_name = entry.file_name().to_string_lossy().to_string();
+				let Some(file_name) = file_name.strip_suffix(".toml") else {
+					return Ok(());
+				};
+				let file_name = file_name.to_string();
 				let mut path = entry.path();
-				// Since `maps_path` is garuanteed to be a path, this unwrap is safe.
-				let file_name = path.file_prefix().unwrap().to_string_lossy().to_string();
 				path.pop();
 				if ui.button(&file_name) {
-					match MapEditor::open(&path, file_name) {
-						Ok(editor) => map_editor = Some(editor),
+					// Switch to maps which are already open rather than discarding their changes.
+					if let Some(index) = map_tabs.find(&file_name) {
+						map_tabs.active = index;
+						map_tabs.select = Some(index);
+						ui.close_current_popup();
+						return Ok(());
+					}
+					match MapEditor::open(&path, file_name, &class_editor.classes, &factions) {
+						Ok(editor) => {
+							map_tabs.open(editor);
+						}
 						Err(msg) => {
 							warning_message = format!("Cannot load level: {msg}");
 							warning_popup.open();
@@ -364,6 +1634,196 @@ fn main() -> Result<(), Box<dyn Error>> {
 			}
 		});
 
+		let recovering = recovery_popup.build(&ui, "Recover Unsaved Work", || {
+			ui.text("The editor didn't close properly last time.");
+			ui.text("The following were autosaved, but never saved:");
+			if recovery.classes {
+				ui.bullet_text("Classes");
+			}
+			if recovery.terrain {
+				ui.bullet_text("Terrain");
+			}
+			if recovery.items {
+				ui.bullet_text("Items");
+			}
+			if recovery.characters {
+				ui.bullet_text("Characters");
+			}
+			for map in &recovery.maps {
+				ui.bullet_text(format!("Map: {map}"));
+			}
+
+			if ui.button("Restore") {
+				problems_stale = true;
+				if let Err(msg) = restore(
+					&config.save_path,
+					&recovery,
+					Editors {
+						classes: &mut class_editor,
+						terrain: &mut terrain_editor,
+						items: &mut item_editor,
+						characters: &mut character_editor,
+						map_tabs: &mut map_tabs,
+					},
+					&factions,
+					texture_atlas.len(),
+				) {
+					warning_message = format!("Failed to restore unsaved work: {msg}");
+					warning_popup.open();
+				}
+				ui.close_current_popup();
+			}
+			ui.same_line();
+			if ui.button("Discard") {
+				if let Err(msg) = fs::remove_dir_all(append_path(&config.save_path, RECOVERY_DIR)) {
+					warning_message = format!("Failed to discard unsaved work: {msg}");
+					warning_popup.open();
+				}
+				ui.close_current_popup();
+			}
+		});
+		recovery_popup.reset();
+		// Only one modal can be shown at a time, so wait for the recovery popup to be answered.
+		if recovering.is_none() {
+			if let Some(msg) = startup_warning.take() {
+				warning_message = msg;
+				warning_popup.open();
+			}
+		}
+
+		for path in file_watcher.poll() {
+			problems_stale = true;
+			if path == tileset_path {
+				match load_tileset(&tileset_path, &config.tileset, display, textures) {
+					Ok(new_atlas) => {
+						textures.remove(texture_atlas.texture);
+						texture_atlas = new_atlas;
+						terrain_editor.grow_tiles(texture_atlas.len());
+						missing_textures.forget_tiles(texture_atlas.len());
+						if selected_tile != EMPTY_TILE {
+							selected_tile = selected_tile.min(texture_atlas.len() - 1);
+						}
+						eprintln!("Reloaded tileset");
+					}
+					Err(msg) => {
+						warning_message = format!("Failed to reload tileset: {msg}");
+						warning_popup.open();
+					}
+				}
+			} else if path == unit_icons_path {
+				for texture in unit_icons.values() {
+					textures.remove(*texture);
+				}
+				unit_icons = load_icons(&unit_icons_path, display, textures);
+				missing_textures.forget_icons(&unit_icons);
+				if !unit_icons.contains_key(&class_editor.default_icon) {
+					if let Some(icon) = unit_icons.keys().min() {
+						class_editor.default_icon = icon.clone();
+					}
+				}
+				eprintln!("Reloaded unit icons");
+			} else if path == item_icons_path {
+				for texture in item_icons.values() {
+					textures.remove(*texture);
+				}
+				item_icons = load_icons(&item_icons_path, display, textures);
+				missing_textures.forget_icons(&item_icons);
+				eprintln!("Reloaded item icons");
+			} else if path == portraits_path {
+				for texture in portraits.values() {
+					textures.remove(*texture);
+				}
+				portraits = load_icons(&portraits_path, display, textures);
+				missing_textures.forget_icons(&portraits);
+				eprintln!("Reloaded portraits");
+			} else if path == classes_path {
+				// Saving changes the file too, so only reload it if it's different from the editor.
+				let text = fs::read_to_string(&classes_path).ok();
+				if text.is_some() && text != class_editor.to_toml().ok() {
+					if class_editor.save_state.is_saved() {
+						reload_classes = true;
+					} else {
+						classes_changed_popup.open();
+					}
+				}
+			}
+		}
+
+		classes_changed_popup.build(&ui, "Classes Changed", || {
+			ui.text("classes.toml was changed outside of the editor,");
+			ui.text("but the classes have unsaved changes here too.");
+			if ui.button("Reload") {
+				reload_classes = true;
+				ui.close_current_popup();
+			}
+			ui.hover_tooltip("Discard the changes made in the editor");
+			ui.same_line();
+			if ui.button("Keep Editor's Changes") {
+				ui.close_current_popup();
+			}
+			ui.hover_tooltip("The file will be overwritten when the project is next saved");
+		});
+
+		if reload_classes {
+			match ClassEditor::open(&classes_path, class_editor.default_icon.clone()) {
+				Ok(editor) => {
+					class_editor = editor;
+					problems_stale = true;
+					eprintln!("Reloaded classes");
+				}
+				Err(msg) => {
+					warning_message = format!("Failed to reload classes: {msg}");
+					warning_popup.open();
+				}
+			}
+		}
+
+		if last_autosave.elapsed() >= AUTOSAVE_INTERVAL {
+			last_autosave = Instant::now();
+			match autosave(
+				&config.save_path,
+				Editors {
+					classes: &mut class_editor,
+					terrain: &mut terrain_editor,
+					items: &mut item_editor,
+					characters: &mut character_editor,
+					map_tabs: &mut map_tabs,
+				},
+			) {
+				Ok(()) => autosave_error = None,
+				Err(msg) => {
+					eprintln!("Autosave failed: {msg}");
+					autosave_error = Some(msg.to_string());
+				}
+			}
+		}
+
+		// Mark the title if anything is unsaved, and note whether it's at least been autosaved.
+		let save_states = [
+			&class_editor.save_state,
+			&terrain_editor.save_state,
+			&item_editor.save_state,
+			&character_editor.save_state,
+		]
+		.into_iter()
+		.chain(map_tabs.editors.iter().map(|m| &m.save_state));
+		let mut title = String::from(WINDOW_TITLE);
+		if let Some(map_editor) = map_tabs.active() {
+			title += &format!(" - {}", map_editor.data.name);
+		}
+		if save_states.clone().any(|s| !s.is_saved()) {
+			title += " *";
+			if autosave_error.is_some() {
+				title += " (autosave failed)";
+			} else if save_states.clone().all(|s| s.is_autosaved()) {
+				title += " (autosaved)";
+			}
+		}
+		if title != window_title {
+			window.set_title(&title);
+			window_title = title;
+		}
+
 		warning_popup.build(&ui, "Warning!", || {
 			ui.dummy([300.0, 0.0]);
 			ui.text(&warning_message);
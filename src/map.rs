@@ -1,44 +1,1257 @@
+use crate::toml_helpers::*;
+use crate::{Character, ClassEntry, Factions, History, SaveState, StatOverrides, Stats, MAX_LEVEL};
 use fe_data::*;
 use std::error::Error;
+use std::fs;
 use std::path::Path;
+use toml::*;
+use uuid::Uuid;
+
+/// Marks an empty tile on an overlay layer. The ground layer can't contain empty tiles.
+pub const EMPTY_TILE: usize = usize::MAX;
 
 #[derive(Debug)]
 pub struct MapEditor {
 	// Data
 	pub data: MapData,
+	/// The map's layers, from the ground up.
+	pub layers: Vec<MapLayer>,
+	/// The map's units. `data.units` is always left empty; see `Unit`.
+	pub units: Vec<Unit>,
+	pub history: History<MapEdit>,
+	pub save_state: SaveState,
 	// UI fields
 	pub scroll: [f32; 2],
 	pub zoom: f32,
 	pub info_popup: MapInfoPopup,
+	/// The layer that tools draw onto.
+	pub active_layer: usize,
+	/// Tiles painted since the mouse was pressed; committed to `history` once it's released.
+	pub stroke: Vec<TileChange>,
+	/// The tile where the current rectangle drag began.
+	pub drag_start: Option<(usize, usize)>,
+	/// The last tile the current rectangle drag passed over.
+	pub drag_end: (usize, usize),
+	pub selection: Option<TileRect>,
+	/// A region waiting to be placed by clicking on the map.
+	pub paste: Option<MapRegion>,
+	/// The position of the unit shown in the unit inspector.
+	pub inspected: Option<(u32, u32)>,
+	/// The inspected unit before its current field started being edited.
+	pub inspector_edit: Option<Unit>,
+	/// The active layer's settings before its current field started being edited.
+	pub layer_edit: Option<LayerSettings>,
 }
 
 impl MapEditor {
-	pub fn open(path: impl AsRef<Path>, name: String) -> Result<Self, Box<dyn Error>> {
+	/// Opens a map. `classes` and `factions` are needed to convert units from older maps.
+	/// Units whose class doesn't exist are still loaded, and are reported by `lint_map`.
+	pub fn open(
+		path: impl AsRef<Path>,
+		name: String,
+		classes: &[ClassEntry],
+		factions: &Factions,
+	) -> Result<Self, Box<dyn Error>> {
+		let mut data = MapData::open(&path, name.clone())?;
+
+		// Layers are stored alongside the map data, in the same file.
+		// Names may contain dots, so the extension is added rather than set.
+		let file_path = path.as_ref().join(format!("{name}.toml"));
+		let toml: Table = fs::read_to_string(file_path)?.parse()?;
+		let layers = match toml.get("layers") {
+			Some(Value::Array(layers)) => {
+				let mut result = Vec::new();
+				for (i, layer) in layers.iter().enumerate() {
+					let Value::Table(layer) = layer else {
+						Err(FeError::from("Layer is not a table"))?
+					};
+					result.push(MapLayer::from_table(layer, i == 0, &data)?);
+				}
+				result
+			}
+			Some(_) => Err(FeError::from("Layers are not an array"))?,
+			None => vec![MapLayer::ground()],
+		};
+		if layers.is_empty() {
+			Err(FeError::from("Map has no ground layer"))?;
+		}
+
+		// Maps saved before units referred to classes by UUID only have `data.units`.
+		let mut units = Vec::new();
+		for unit in data.units.drain(..) {
+			units.push(Unit::from_legacy(
+				&unit,
+				classes,
+				factions.default_faction(),
+			));
+		}
+		if toml.contains_key("unit") {
+			for unit in get_array(&toml, "unit")? {
+				let Value::Table(unit) = unit else {
+					Err(FeError::from("Unit is not a table"))?
+				};
+				units.push(Unit::from_table(unit, factions.default_faction())?);
+			}
+		}
+
 		Ok(Self {
-			data: MapData::open(path, name)?,
+			data,
+			layers,
+			units,
+			history: History::new(),
+			save_state: SaveState::new(),
 			// UI stuff
 			scroll: [0.0, 0.0],
 			zoom: 64.0,
 			info_popup: MapInfoPopup::new(),
+			active_layer: 0,
+			stroke: Vec::new(),
+			drag_start: None,
+			drag_end: (0, 0),
+			selection: None,
+			paste: None,
+			inspected: None,
+			inspector_edit: None,
+			layer_edit: None,
 		})
 	}
 
 	pub fn with_size(name: String, width: usize, height: usize) -> Self {
 		Self {
 			data: MapData::with_size(name, width, height),
+			layers: vec![MapLayer::ground()],
+			units: Vec::new(),
+			history: History::new(),
+			// New maps haven't been written anywhere yet.
+			save_state: {
+				let mut save_state = SaveState::new();
+				save_state.mark_unsaved();
+				save_state
+			},
 			scroll: [0.0, 0.0],
 			zoom: 64.0,
 			info_popup: MapInfoPopup::new(),
+			active_layer: 0,
+			stroke: Vec::new(),
+			drag_start: None,
+			drag_end: (0, 0),
+			selection: None,
+			paste: None,
+			inspected: None,
+			inspector_edit: None,
+			layer_edit: None,
+		}
+	}
+
+	pub fn to_toml(&self) -> Result<String, Box<dyn Error>> {
+		let mut toml: Table = self.data.to_toml()?.parse()?;
+		toml.insert(
+			"unit".into(),
+			Value::Array(
+				self.units
+					.iter()
+					.map(|unit| Value::Table(unit.to_table()))
+					.collect(),
+			),
+		);
+		toml.insert(
+			"layers".into(),
+			Value::Array(
+				self.layers
+					.iter()
+					.map(|layer| Value::Table(layer.to_table()))
+					.collect(),
+			),
+		);
+		Ok(toml::to_string(&toml)?)
+	}
+
+	pub fn get_tile(&mut self, layer: usize, x: usize, y: usize) -> &mut usize {
+		let width = self.data.width;
+		&mut layer_tiles(&mut self.data, &mut self.layers, layer)[x + y * width]
+	}
+
+	/// Returns false if the active layer is locked, or if `tile` can't be placed on it.
+	pub fn can_paint(&self, tile: usize) -> bool {
+		// The bottom layer can't have holes in it.
+		!(self.layers[self.active_layer].locked || (self.active_layer == 0 && tile == EMPTY_TILE))
+	}
+
+	/// Sets a tile on the active layer as part of the current brush stroke.
+	pub fn paint(&mut self, x: usize, y: usize, tile: usize) {
+		if !self.can_paint(tile) {
+			return;
+		}
+
+		let layer = self.active_layer;
+		let index = x + y * self.data.width;
+		let tiles = layer_tiles(&mut self.data, &mut self.layers, layer);
+		let from = tiles[index];
+		if from != tile {
+			tiles[index] = tile;
+			self.save_state.mark_unsaved();
+			self.stroke.push(TileChange {
+				layer,
+				index,
+				from,
+				to: tile,
+			});
+		}
+	}
+
+	/// Commits the current brush stroke as a single history entry.
+	pub fn end_stroke(&mut self) {
+		if !self.stroke.is_empty() {
+			let stroke = std::mem::take(&mut self.stroke);
+			self.history.push(MapEdit::Tiles(stroke));
+		}
+	}
+
+	/// Fills a rectangle with a single tile as one history entry.
+	pub fn fill_rect(&mut self, rect: TileRect, tile: usize) {
+		self.end_stroke();
+		for y in rect.y..rect.y + rect.height {
+			for x in rect.x..rect.x + rect.width {
+				self.paint(x, y, tile);
+			}
+		}
+		self.end_stroke();
+	}
+
+	/// Replaces the region of matching tiles connected to (x, y) as one history entry.
+	pub fn flood_fill(&mut self, x: usize, y: usize, tile: usize) {
+		let layer = self.active_layer;
+		let target = *self.get_tile(layer, x, y);
+		if target == tile || !self.can_paint(tile) {
+			return;
+		}
+
+		self.end_stroke();
+		let mut stack = vec![(x, y)];
+		while let Some((x, y)) = stack.pop() {
+			if *self.get_tile(layer, x, y) != target {
+				continue;
+			}
+			self.paint(x, y, tile);
+			if x > 0 {
+				stack.push((x - 1, y));
+			}
+			if y > 0 {
+				stack.push((x, y - 1));
+			}
+			if x + 1 < self.data.width {
+				stack.push((x + 1, y));
+			}
+			if y + 1 < self.data.height {
+				stack.push((x, y + 1));
+			}
+		}
+		self.end_stroke();
+	}
+
+	/// Replaces every instance of a tile on the active layer as one history entry.
+	pub fn replace_all(&mut self, from: usize, to: usize) {
+		self.end_stroke();
+		for y in 0..self.data.height {
+			for x in 0..self.data.width {
+				if *self.get_tile(self.active_layer, x, y) == from {
+					self.paint(x, y, to);
+				}
+			}
+		}
+		self.end_stroke();
+	}
+
+	/// Copies a rectangle of every layer, along with any units and spawns inside of it.
+	pub fn copy_region(&self, rect: TileRect) -> MapRegion {
+		let mut layers = Vec::new();
+		for layer in 0..self.layers.len() {
+			let source = self.tiles(layer);
+			let mut tiles = Vec::new();
+			for y in rect.y..rect.y + rect.height {
+				for x in rect.x..rect.x + rect.width {
+					tiles.push(source[x + y * self.data.width]);
+				}
+			}
+			layers.push(tiles);
+		}
+
+		let units = self
+			.units
+			.iter()
+			.filter(|u| rect.contains(u.x as usize, u.y as usize))
+			.map(|u| Unit {
+				x: u.x - rect.x as u32,
+				y: u.y - rect.y as u32,
+				..u.clone()
+			})
+			.collect();
+
+		let spawns = self
+			.data
+			.spawns
+			.iter()
+			.filter(|s| rect.contains(s.0 as usize, s.1 as usize))
+			.map(|s| (s.0 - rect.x as u32, s.1 - rect.y as u32))
+			.collect();
+
+		MapRegion {
+			width: rect.width,
+			height: rect.height,
+			layers,
+			units,
+			spawns,
+		}
+	}
+
+	/// Returns a layer's tiles; the ground layer's tiles are the map's data.
+	pub fn tiles(&self, layer: usize) -> &Vec<usize> {
+		if layer == 0 {
+			&self.data.data
+		} else {
+			&self.layers[layer].tiles
+		}
+	}
+
+	/// Fills a rectangle of the ground with a single tile, empties it on all other unlocked layers,
+	/// and removes its units and spawns, as one history entry.
+	pub fn clear_region(&mut self, rect: TileRect, tile: usize) {
+		self.end_stroke();
+		let mut edits = Vec::new();
+		self.remove_contents(rect, &mut edits);
+
+		let mut changes = Vec::new();
+		for layer in 0..self.layers.len() {
+			// The ground can't be emptied, so leave it as it is.
+			if self.layers[layer].locked || (layer == 0 && tile == EMPTY_TILE) {
+				continue;
+			}
+			let to = if layer == 0 { tile } else { EMPTY_TILE };
+			let tiles = self.tiles(layer);
+			for y in rect.y..rect.y + rect.height {
+				for x in rect.x..rect.x + rect.width {
+					let index = x + y * self.data.width;
+					if tiles[index] != to {
+						changes.push(TileChange {
+							layer,
+							index,
+							from: tiles[index],
+							to,
+						});
+					}
+				}
+			}
+		}
+		self.apply_into(&mut edits, MapEdit::Tiles(changes));
+
+		self.record(MapEdit::Batch(edits));
+	}
+
+	/// Pastes a region with its top-left corner at (x, y), replacing anything beneath it.
+	/// Returns the area that was pasted; anything which falls outside of the map is discarded,
+	/// as are layers which are locked or don't exist on this map.
+	pub fn paste_region(&mut self, region: &MapRegion, x: usize, y: usize) -> TileRect {
+		let rect = TileRect {
+			x,
+			y,
+			width: region.width.min(self.data.width.saturating_sub(x)),
+			height: region.height.min(self.data.height.saturating_sub(y)),
+		};
+
+		self.end_stroke();
+		let mut edits = Vec::new();
+		self.remove_contents(rect, &mut edits);
+
+		let mut changes = Vec::new();
+		for (layer, source) in region.layers.iter().enumerate() {
+			if layer >= self.layers.len() || self.layers[layer].locked {
+				continue;
+			}
+			let tiles = self.tiles(layer);
+			for ry in 0..rect.height {
+				for rx in 0..rect.width {
+					let index = (x + rx) + (y + ry) * self.data.width;
+					let tile = source[rx + ry * region.width];
+					// The ground can't be emptied, so leave it as it is.
+					if tiles[index] != tile && !(layer == 0 && tile == EMPTY_TILE) {
+						changes.push(TileChange {
+							layer,
+							index,
+							from: tiles[index],
+							to: tile,
+						});
+					}
+				}
+			}
+		}
+		self.apply_into(&mut edits, MapEdit::Tiles(changes));
+
+		for unit in &region.units {
+			if rect.contains(x + unit.x as usize, y + unit.y as usize) {
+				let unit = Unit {
+					x: x as u32 + unit.x,
+					y: y as u32 + unit.y,
+					..unit.clone()
+				};
+				self.apply_into(&mut edits, MapEdit::InsertUnit(self.units.len(), unit));
+			}
+		}
+
+		for spawn in &region.spawns {
+			if rect.contains(x + spawn.0 as usize, y + spawn.1 as usize) {
+				let spawn = (x as u32 + spawn.0, y as u32 + spawn.1);
+				self.apply_into(
+					&mut edits,
+					MapEdit::InsertSpawn(self.data.spawns.len(), spawn),
+				);
+			}
+		}
+
+		self.record(MapEdit::Batch(edits));
+		rect
+	}
+
+	/// Removes all units and spawns within a rectangle.
+	fn remove_contents(&mut self, rect: TileRect, edits: &mut Vec<MapEdit>) {
+		while let Some(index) = self
+			.units
+			.iter()
+			.position(|u| rect.contains(u.x as usize, u.y as usize))
+		{
+			let unit = self.units[index].clone();
+			self.apply_into(edits, MapEdit::RemoveUnit(index, unit));
+		}
+
+		while let Some(index) = self
+			.data
+			.spawns
+			.iter()
+			.position(|s| rect.contains(s.0 as usize, s.1 as usize))
+		{
+			let spawn = self.data.spawns[index];
+			self.apply_into(edits, MapEdit::RemoveSpawn(index, spawn));
+		}
+	}
+
+	/// Applies an edit which will be recorded later as part of a batch.
+	fn apply_into(&mut self, edits: &mut Vec<MapEdit>, edit: MapEdit) {
+		edit.apply(&mut self.data, &mut self.layers, &mut self.units);
+		edits.push(edit);
+	}
+
+	/// Applies an edit to the map and records it.
+	pub fn apply(&mut self, edit: MapEdit) {
+		edit.apply(&mut self.data, &mut self.layers, &mut self.units);
+		self.record(edit);
+	}
+
+	/// Adds an empty layer above all others and makes it active.
+	pub fn add_layer(&mut self) {
+		let layer = MapLayer::with_size(
+			format!("Layer {}", self.layers.len()),
+			self.data.width,
+			self.data.height,
+		);
+		self.apply(MapEdit::InsertLayer(self.layers.len(), layer));
+		self.active_layer = self.layers.len() - 1;
+	}
+
+	/// Removes a layer. The ground layer can't be removed.
+	pub fn remove_layer(&mut self, layer: usize) {
+		if layer != 0 {
+			self.apply(MapEdit::RemoveLayer(layer, self.layers[layer].clone()));
+			self.clamp_selection();
+		}
+	}
+
+	/// Records an edit which has already been made to the map.
+	pub fn record(&mut self, edit: MapEdit) {
+		self.end_stroke();
+		self.history.push(edit);
+		self.save_state.mark_unsaved();
+	}
+
+	pub fn mark_saved(&mut self) {
+		self.end_stroke();
+		self.history.mark_saved();
+		self.save_state.mark_saved();
+	}
+
+	/// Returns the index of the unit shown in the unit inspector, if it's still there.
+	pub fn inspected_unit(&self) -> Option<usize> {
+		let position = self.inspected?;
+		self.units.iter().position(|u| (u.x, u.y) == position)
+	}
+
+	/// Scrolls the map so that a tile is in the middle of a view of `view_size`, and selects it.
+	pub fn reveal(&mut self, layer: Option<usize>, x: usize, y: usize, view_size: [f32; 2]) {
+		self.scroll = [
+			view_size[0] / 2.0 - (x as f32 + 0.5) * self.zoom,
+			view_size[1] / 2.0 - (y as f32 + 0.5) * self.zoom,
+		];
+		if let Some(layer) = layer.filter(|&l| l < self.layers.len()) {
+			self.active_layer = layer;
+		}
+		if x < self.data.width && y < self.data.height {
+			self.paste = None;
+			self.selection = Some(TileRect {
+				x,
+				y,
+				width: 1,
+				height: 1,
+			});
+		}
+	}
+
+	/// Copies the entire map as a region.
+	pub fn to_region(&self) -> MapRegion {
+		self.copy_region(TileRect {
+			x: 0,
+			y: 0,
+			width: self.data.width,
+			height: self.data.height,
+		})
+	}
+
+	/// Computes the map's contents after resizing it and moving everything by `offset` tiles.
+	/// New ground tiles are filled with `fill`, and other layers are left empty.
+	pub fn transformed(
+		&self,
+		width: usize,
+		height: usize,
+		offset: (isize, isize),
+		fill: usize,
+	) -> MapRegion {
+		let mut region = MapRegion {
+			width,
+			height,
+			layers: Vec::new(),
+			units: Vec::new(),
+			spawns: Vec::new(),
+		};
+
+		for layer in 0..self.layers.len() {
+			let source = self.tiles(layer);
+			let mut tiles = vec![if layer == 0 { fill } else { EMPTY_TILE }; width * height];
+			for y in 0..self.data.height {
+				for x in 0..self.data.width {
+					if let Some((tx, ty)) = translate(x as u32, y as u32, offset, (width, height)) {
+						tiles[tx as usize + ty as usize * width] = source[x + y * self.data.width];
+					}
+				}
+			}
+			region.layers.push(tiles);
+		}
+
+		for unit in &self.units {
+			if let Some((x, y)) = translate(unit.x, unit.y, offset, (width, height)) {
+				region.units.push(Unit {
+					x,
+					y,
+					..unit.clone()
+				});
+			}
+		}
+
+		for spawn in &self.data.spawns {
+			if let Some(spawn) = translate(spawn.0, spawn.1, offset, (width, height)) {
+				region.spawns.push(spawn);
+			}
+		}
+
+		region
+	}
+
+	/// Returns the units and spawns which `transformed` would push off of the map,
+	/// without building the rest of the new map.
+	pub fn lost_contents(
+		&self,
+		width: usize,
+		height: usize,
+		offset: (isize, isize),
+	) -> (Vec<&Unit>, Vec<(u32, u32)>) {
+		let units = self
+			.units
+			.iter()
+			.filter(|u| translate(u.x, u.y, offset, (width, height)).is_none())
+			.collect();
+		let spawns = self
+			.data
+			.spawns
+			.iter()
+			.filter(|s| translate(s.0, s.1, offset, (width, height)).is_none())
+			.copied()
+			.collect();
+		(units, spawns)
+	}
+
+	/// Replaces the map's size and contents with a region.
+	pub fn replace_contents(&mut self, region: MapRegion) {
+		self.end_stroke();
+		self.apply(MapEdit::Replace {
+			from: self.to_region(),
+			to: region,
+		});
+		self.clamp_selection();
+	}
+
+	/// Drops the selection if it no longer fits on the map,
+	/// and makes sure the active layer still exists.
+	fn clamp_selection(&mut self) {
+		self.active_layer = self.active_layer.min(self.layers.len() - 1);
+		if let Some(selection) = self.selection {
+			if selection.x + selection.width > self.data.width
+				|| selection.y + selection.height > self.data.height
+			{
+				self.selection = None;
+			}
+		}
+	}
+
+	pub fn undo(&mut self) {
+		self.end_stroke();
+		if let Some(edit) = self.history.undo() {
+			edit.revert(&mut self.data, &mut self.layers, &mut self.units);
+			self.save_state.sync_with(&self.history);
+		}
+		self.clamp_selection();
+	}
+
+	pub fn redo(&mut self) {
+		self.end_stroke();
+		if let Some(edit) = self.history.redo() {
+			edit.apply(&mut self.data, &mut self.layers, &mut self.units);
+			self.save_state.sync_with(&self.history);
+		}
+		self.clamp_selection();
+	}
+}
+
+/// Which part of a map stays in place when it's resized.
+/// Each axis is 0 for the left or top edge, 1 for the center, and 2 for the right or bottom edge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Anchor {
+	pub x: usize,
+	pub y: usize,
+}
+
+impl Anchor {
+	/// Returns how far the map's contents must move to stay anchored after a resize.
+	pub fn offset(&self, from: (usize, usize), to: (usize, usize)) -> (isize, isize) {
+		(
+			(to.0 as isize - from.0 as isize) * self.x as isize / 2,
+			(to.1 as isize - from.1 as isize) * self.y as isize / 2,
+		)
+	}
+}
+
+/// Converts a position on a map to one on the map after it's resized to `size`
+/// and everything is moved by `offset` tiles, if it's still in bounds.
+fn translate(x: u32, y: u32, offset: (isize, isize), size: (usize, usize)) -> Option<(u32, u32)> {
+	let x = x as isize + offset.0;
+	let y = y as isize + offset.1;
+	if x >= 0 && y >= 0 && (x as usize) < size.0 && (y as usize) < size.1 {
+		Some((x as u32, y as u32))
+	} else {
+		None
+	}
+}
+
+/// Returns a layer's tiles; the ground layer's tiles are the map's data.
+fn layer_tiles<'a>(
+	map: &'a mut MapData,
+	layers: &'a mut [MapLayer],
+	layer: usize,
+) -> &'a mut Vec<usize> {
+	if layer == 0 {
+		&mut map.data
+	} else {
+		&mut layers[layer].tiles
+	}
+}
+
+fn tile_value(tile: usize) -> Value {
+	if tile == EMPTY_TILE {
+		Value::Integer(-1)
+	} else {
+		usize_value(tile)
+	}
+}
+
+fn as_tile(value: &Value) -> Result<usize, Box<dyn Error>> {
+	if let Value::Integer(-1) = value {
+		Ok(EMPTY_TILE)
+	} else {
+		as_usize(value, "tile")
+	}
+}
+
+/// A named layer of tiles. Layers are drawn from the ground up.
+#[derive(Clone, Debug)]
+pub struct MapLayer {
+	pub name: String,
+	/// The layer's tiles, which may be `EMPTY_TILE`.
+	/// The ground layer's tiles are stored in `MapData.data` instead, so this is left empty.
+	pub tiles: Vec<usize>,
+	pub visible: bool,
+	pub locked: bool,
+	/// Whether the layer is drawn over units, such as for roofs and tree tops.
+	pub above_units: bool,
+	pub opacity: f32,
+}
+
+impl MapLayer {
+	pub fn ground() -> Self {
+		Self {
+			name: String::from("Ground"),
+			tiles: Vec::new(),
+			visible: true,
+			locked: false,
+			above_units: false,
+			opacity: 1.0,
 		}
 	}
 
-	pub fn get_tile(&mut self, x: usize, y: usize) -> &mut usize {
-		&mut self.data.data[x + y * self.data.width]
+	pub fn with_size(name: String, width: usize, height: usize) -> Self {
+		Self {
+			name,
+			tiles: vec![EMPTY_TILE; width * height],
+			..Self::ground()
+		}
+	}
+
+	pub fn from_table(
+		table: &Table,
+		is_ground: bool,
+		map: &MapData,
+	) -> Result<Self, Box<dyn Error>> {
+		let mut layer = Self::ground();
+		layer.name = get_string(table, "name")?;
+		if let Some(Value::Boolean(visible)) = table.get("visible") {
+			layer.visible = *visible;
+		}
+		if let Some(Value::Boolean(locked)) = table.get("locked") {
+			layer.locked = *locked;
+		}
+		if let Some(Value::Boolean(above_units)) = table.get("above_units") {
+			layer.above_units = *above_units;
+		}
+		if let Some(Value::Float(opacity)) = table.get("opacity") {
+			layer.opacity = *opacity as f32;
+		}
+
+		if !is_ground {
+			for tile in get_array(table, "tiles")? {
+				layer.tiles.push(as_tile(tile)?);
+			}
+			if layer.tiles.len() != map.width * map.height {
+				Err(FeError::from(format!(
+					"Layer \"{}\" does not match the map's size",
+					layer.name
+				)))?;
+			}
+		}
+
+		Ok(layer)
+	}
+
+	pub fn settings(&self) -> LayerSettings {
+		LayerSettings {
+			name: self.name.clone(),
+			visible: self.visible,
+			locked: self.locked,
+			above_units: self.above_units,
+			opacity: self.opacity,
+		}
+	}
+
+	pub fn set_settings(&mut self, settings: &LayerSettings) {
+		self.name = settings.name.clone();
+		self.visible = settings.visible;
+		self.locked = settings.locked;
+		self.above_units = settings.above_units;
+		self.opacity = settings.opacity;
+	}
+
+	pub fn to_table(&self) -> Table {
+		let mut table = Table::new();
+		table.insert("name".into(), Value::String(self.name.clone()));
+		table.insert("visible".into(), Value::Boolean(self.visible));
+		table.insert("locked".into(), Value::Boolean(self.locked));
+		table.insert("above_units".into(), Value::Boolean(self.above_units));
+		table.insert("opacity".into(), Value::Float(self.opacity as f64));
+		if !self.tiles.is_empty() {
+			table.insert(
+				"tiles".into(),
+				Value::Array(self.tiles.iter().map(|t| tile_value(*t)).collect()),
+			);
+		}
+		table
+	}
+}
+
+/// Everything about a layer except for its tiles, so that it can be changed as one edit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LayerSettings {
+	pub name: String,
+	pub visible: bool,
+	pub locked: bool,
+	pub above_units: bool,
+	pub opacity: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MapTool {
+	/// Paints the tile under the cursor.
+	Brush,
+	/// Fills a rectangle of tiles.
+	Rectangle,
+	/// Fills a connected region of matching tiles,
+	/// or every matching tile on the map while Shift is held.
+	Bucket,
+	/// Selects a rectangle of tiles.
+	Select,
+}
+
+impl MapTool {
+	pub const ALL: [MapTool; 4] = [
+		MapTool::Brush,
+		MapTool::Rectangle,
+		MapTool::Bucket,
+		MapTool::Select,
+	];
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			MapTool::Brush => "Brush",
+			MapTool::Rectangle => "Rectangle",
+			MapTool::Bucket => "Bucket",
+			MapTool::Select => "Select",
+		}
+	}
+
+	pub fn shortcut(&self) -> &'static str {
+		match self {
+			MapTool::Brush => "B",
+			MapTool::Rectangle => "R",
+			MapTool::Bucket => "G",
+			MapTool::Select => "M",
+		}
+	}
+}
+
+/// A rectangular region of tiles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileRect {
+	pub x: usize,
+	pub y: usize,
+	pub width: usize,
+	pub height: usize,
+}
+
+impl TileRect {
+	/// Creates the smallest rectangle containing both tiles.
+	pub fn from_corners(a: (usize, usize), b: (usize, usize)) -> Self {
+		Self {
+			x: a.0.min(b.0),
+			y: a.1.min(b.1),
+			width: a.0.abs_diff(b.0) + 1,
+			height: a.1.abs_diff(b.1) + 1,
+		}
+	}
+
+	pub fn contains(&self, x: usize, y: usize) -> bool {
+		x >= self.x && y >= self.y && x < self.x + self.width && y < self.y + self.height
+	}
+}
+
+#[derive(Debug)]
+pub struct TileChange {
+	pub layer: usize,
+	pub index: usize,
+	pub from: usize,
+	pub to: usize,
+}
+
+/// A unit placed on a map.
+///
+/// `MapUnit` refers to its class by index, which changes whenever a class is deleted,
+/// so the editor keeps its own units which refer to classes by UUID instead.
+#[derive(Clone, Debug)]
+pub struct Unit {
+	pub x: u32,
+	pub y: u32,
+	pub class: Uuid,
+	/// Only used if the unit isn't a character.
+	pub name: String,
+	/// The character the unit is, if any; see `Character`.
+	pub character: Option<Uuid>,
+	/// The name of the faction the unit fights for; see `Factions`.
+	pub faction: String,
+	pub level: u32,
+	/// Stats which replace the base stats of the unit's class.
+	pub stats: StatOverrides,
+	/// The UUIDs of the items the unit carries, in order; see `Item`.
+	pub items: Vec<Uuid>,
+	/// Whether the unit drops its last item when it's defeated.
+	pub drops_item: bool,
+}
+
+impl Unit {
+	pub fn new(x: u32, y: u32, class: Uuid, faction: &str) -> Self {
+		Self {
+			x,
+			y,
+			class,
+			name: String::new(),
+			character: None,
+			faction: faction.to_string(),
+			level: 1,
+			stats: StatOverrides::default(),
+			items: Vec::new(),
+			drops_item: false,
+		}
+	}
+
+	/// Converts a unit from a map saved before classes had UUIDs,
+	/// where classes were referred to by their position in `classes.toml`.
+	/// Units whose class doesn't exist are given the nil UUID, which no class has.
+	pub fn from_legacy(unit: &MapUnit, classes: &[ClassEntry], faction: &str) -> Self {
+		let class = classes.get(unit.class).map_or(Uuid::nil(), |c| c.uuid);
+		Self {
+			name: unit.name.clone(),
+			..Self::new(unit.x, unit.y, class, faction)
+		}
+	}
+
+	/// Reads a unit, giving it `default_faction` if it was saved before factions were added.
+	pub fn from_table(table: &Table, default_faction: &str) -> Result<Self, Box<dyn Error>> {
+		let x = get_usize(table, "x")? as u32;
+		let y = get_usize(table, "y")? as u32;
+		let class = match Uuid::parse_str(&get_string(table, "class")?) {
+			Ok(class) => class,
+			Err(msg) => Err(FeError::from(format!(
+				"Unit at ({x}, {y}) has an invalid class ID: {msg}"
+			)))?,
+		};
+		let mut unit = Self {
+			name: get_string(table, "name")?,
+			..Self::new(x, y, class, default_faction)
+		};
+		if table.contains_key("faction") {
+			unit.faction = get_string(table, "faction")?;
+		}
+
+		// Everything below was added later, so units without it keep their defaults.
+		if table.contains_key("character") {
+			match Uuid::parse_str(&get_string(table, "character")?) {
+				Ok(character) => unit.character = Some(character),
+				Err(msg) => Err(FeError::from(format!(
+					"Unit at ({x}, {y}) has an invalid character ID: {msg}"
+				)))?,
+			}
+		}
+		if table.contains_key("level") {
+			unit.level = get_usize(table, "level")?.clamp(1, MAX_LEVEL as usize) as u32;
+		}
+		if table.contains_key("stats") {
+			unit.stats = StatOverrides::from_table(get_table(table, "stats")?)?;
+		}
+		if table.contains_key("items") {
+			for item in get_array(table, "items")? {
+				let Value::String(item) = item else {
+					Err(FeError::from(format!(
+						"Unit at ({x}, {y}) has an item which is not a string"
+					)))?
+				};
+				match Uuid::parse_str(item) {
+					Ok(item) => unit.items.push(item),
+					Err(msg) => Err(FeError::from(format!(
+						"Unit at ({x}, {y}) has an invalid item ID: {msg}"
+					)))?,
+				}
+			}
+		}
+		if let Some(drops_item) = table.get("drops_item") {
+			let Value::Boolean(drops_item) = drops_item else {
+				Err(FeError::from(format!(
+					"Unit at ({x}, {y}): drops_item is not a boolean"
+				)))?
+			};
+			unit.drops_item = *drops_item;
+		}
+		Ok(unit)
+	}
+
+	pub fn to_table(&self) -> Table {
+		let mut table = Table::new();
+		table.insert("x".into(), usize_value(self.x as usize));
+		table.insert("y".into(), usize_value(self.y as usize));
+		table.insert("class".into(), Value::String(self.class.to_string()));
+		table.insert("name".into(), Value::String(self.name.clone()));
+		if let Some(character) = self.character {
+			table.insert("character".into(), Value::String(character.to_string()));
+		}
+		table.insert("faction".into(), Value::String(self.faction.clone()));
+		table.insert("level".into(), usize_value(self.level as usize));
+		if !self.stats.is_empty() {
+			table.insert("stats".into(), Value::Table(self.stats.to_table()));
+		}
+		let items = self
+			.items
+			.iter()
+			.map(|i| Value::String(i.to_string()))
+			.collect();
+		table.insert("items".into(), Value::Array(items));
+		table.insert("drops_item".into(), Value::Boolean(self.drops_item));
+		table
+	}
+
+	/// Returns the name of the unit's character, or its own name if it isn't one.
+	pub fn display_name<'a>(&'a self, characters: &'a [Character]) -> &'a str {
+		match self.character {
+			Some(uuid) => match characters.iter().find(|c| c.uuid == uuid) {
+				Some(character) => &character.name,
+				None => "Missing character",
+			},
+			None => &self.name,
+		}
+	}
+
+	/// Returns the stats the unit has before its overrides:
+	/// its character's if it's one, or its class's otherwise.
+	pub fn base_stats(&self, classes: &[ClassEntry], characters: &[Character]) -> Option<Stats> {
+		if let Some(uuid) = self.character {
+			return characters.iter().find(|c| c.uuid == uuid).map(|c| c.bases);
+		}
+		classes
+			.iter()
+			.find(|c| c.uuid == self.class)
+			.map(|c| c.stats.bases)
+	}
+}
+
+/// A copied rectangle of a map, including the units and spawns on it.
+/// Positions are relative to the region's top-left corner.
+#[derive(Clone, Debug)]
+pub struct MapRegion {
+	pub width: usize,
+	pub height: usize,
+	/// The tiles of each layer, from the ground up.
+	pub layers: Vec<Vec<usize>>,
+	pub units: Vec<Unit>,
+	pub spawns: Vec<(u32, u32)>,
+}
+
+impl MapRegion {
+	/// Parses a region from the text produced by `to_toml`,
+	/// allowing regions to be pasted between editors.
+	pub fn from_toml(text: &str, factions: &Factions) -> Result<Self, Box<dyn Error>> {
+		let toml: Table = text.parse()?;
+		let region = get_table(&toml, "region")?;
+
+		let width = get_usize(region, "width")?;
+		let height = get_usize(region, "height")?;
+		// The clipboard could contain anything, so don't trust the size.
+		let Some(tile_count) = width.checked_mul(height) else {
+			Err(FeError::from(format!(
+				"Region is too large ({width}x{height})"
+			)))?
+		};
+		let mut layers = Vec::new();
+		for layer in get_array(region, "layers")? {
+			let Value::Array(layer) = layer else {
+				Err(FeError::from("Layer is not an array"))?
+			};
+			let mut tiles = Vec::new();
+			for tile in layer {
+				tiles.push(as_tile(tile)?);
+			}
+			if tiles.len() != tile_count {
+				Err(FeError::from(format!(
+					"Region should have {tile_count} tiles, but has {}",
+					tiles.len()
+				)))?;
+			}
+			layers.push(tiles);
+		}
+
+		let mut units = Vec::new();
+		for unit in get_array(region, "units")? {
+			match unit {
+				Value::Table(unit) => {
+					units.push(Unit::from_table(unit, factions.default_faction())?)
+				}
+				_ => Err(FeError::from("Unit is not a table"))?,
+			}
+		}
+
+		let mut spawns = Vec::new();
+		for spawn in get_array(region, "spawns")? {
+			match spawn.as_array().map(Vec::as_slice) {
+				Some([x, y]) => spawns.push((as_usize(x, "x")? as u32, as_usize(y, "y")? as u32)),
+				_ => Err(FeError::from("Spawn is not a pair of coordinates"))?,
+			}
+		}
+
+		Ok(Self {
+			width,
+			height,
+			layers,
+			units,
+			spawns,
+		})
+	}
+
+	/// Replaces an entire map with this region.
+	/// The region must have as many layers as the map.
+	pub fn write_to(&self, map: &mut MapData, layers: &mut [MapLayer], units: &mut Vec<Unit>) {
+		map.width = self.width;
+		map.height = self.height;
+		for (layer, tiles) in self.layers.iter().enumerate() {
+			*layer_tiles(map, layers, layer) = tiles.clone();
+		}
+		*units = self.units.clone();
+		map.spawns = self.spawns.clone();
+	}
+
+	pub fn to_toml(&self) -> Result<String, Box<dyn Error>> {
+		let mut region = Table::new();
+		region.insert("width".into(), usize_value(self.width));
+		region.insert("height".into(), usize_value(self.height));
+		region.insert(
+			"layers".into(),
+			Value::Array(
+				self.layers
+					.iter()
+					.map(|tiles| Value::Array(tiles.iter().map(|t| tile_value(*t)).collect()))
+					.collect(),
+			),
+		);
+		region.insert(
+			"units".into(),
+			Value::Array(
+				self.units
+					.iter()
+					.map(|u| Value::Table(u.to_table()))
+					.collect(),
+			),
+		);
+		region.insert(
+			"spawns".into(),
+			Value::Array(
+				self.spawns
+					.iter()
+					.map(|s| {
+						Value::Array(vec![usize_value(s.0 as usize), usize_value(s.1 as usize)])
+					})
+					.collect(),
+			),
+		);
+
+		let mut toml = Table::new();
+		toml.insert("region".into(), Value::Table(region));
+		Ok(toml::to_string(&toml)?)
+	}
+}
+
+/// A reversible change to a map.
+#[derive(Debug)]
+pub enum MapEdit {
+	/// A set of tiles changed by a single stroke.
+	Tiles(Vec<TileChange>),
+	InsertUnit(usize, Unit),
+	RemoveUnit(usize, Unit),
+	ModifyUnit {
+		index: usize,
+		from: Unit,
+		to: Unit,
+	},
+	InsertSpawn(usize, (u32, u32)),
+	RemoveSpawn(usize, (u32, u32)),
+	/// Several edits made at once, such as by pasting.
+	Batch(Vec<MapEdit>),
+	/// A change to the map's size, which may move or remove anything on it.
+	Replace {
+		from: MapRegion,
+		to: MapRegion,
+	},
+	InsertLayer(usize, MapLayer),
+	RemoveLayer(usize, MapLayer),
+	ModifyLayer {
+		index: usize,
+		from: LayerSettings,
+		to: LayerSettings,
+	},
+}
+
+impl MapEdit {
+	pub fn apply(&self, map: &mut MapData, layers: &mut Vec<MapLayer>, units: &mut Vec<Unit>) {
+		match self {
+			MapEdit::Tiles(changes) => {
+				for change in changes {
+					layer_tiles(map, layers, change.layer)[change.index] = change.to;
+				}
+			}
+			MapEdit::InsertUnit(index, unit) => units.insert(*index, unit.clone()),
+			MapEdit::RemoveUnit(index, _) => {
+				units.remove(*index);
+			}
+			MapEdit::ModifyUnit { index, to, .. } => units[*index] = to.clone(),
+			MapEdit::InsertSpawn(index, spawn) => map.spawns.insert(*index, *spawn),
+			MapEdit::RemoveSpawn(index, _) => {
+				map.spawns.remove(*index);
+			}
+			MapEdit::Batch(edits) => {
+				for edit in edits {
+					edit.apply(map, layers, units);
+				}
+			}
+			MapEdit::Replace { to, .. } => to.write_to(map, layers, units),
+			MapEdit::InsertLayer(index, layer) => layers.insert(*index, layer.clone()),
+			MapEdit::RemoveLayer(index, _) => {
+				layers.remove(*index);
+			}
+			MapEdit::ModifyLayer { index, to, .. } => layers[*index].set_settings(to),
+		}
+	}
+
+	pub fn revert(&self, map: &mut MapData, layers: &mut Vec<MapLayer>, units: &mut Vec<Unit>) {
+		match self {
+			MapEdit::Tiles(changes) => {
+				// Tiles may have been painted more than once during a stroke,
+				// so undo them in reverse to get back the original.
+				for change in changes.iter().rev() {
+					layer_tiles(map, layers, change.layer)[change.index] = change.from;
+				}
+			}
+			MapEdit::InsertUnit(index, _) => {
+				units.remove(*index);
+			}
+			MapEdit::RemoveUnit(index, unit) => units.insert(*index, unit.clone()),
+			MapEdit::ModifyUnit { index, from, .. } => units[*index] = from.clone(),
+			MapEdit::InsertSpawn(index, _) => {
+				map.spawns.remove(*index);
+			}
+			MapEdit::RemoveSpawn(index, spawn) => map.spawns.insert(*index, *spawn),
+			MapEdit::Batch(edits) => {
+				for edit in edits.iter().rev() {
+					edit.revert(map, layers, units);
+				}
+			}
+			MapEdit::Replace { from, .. } => from.write_to(map, layers, units),
+			MapEdit::InsertLayer(index, _) => {
+				layers.remove(*index);
+			}
+			MapEdit::RemoveLayer(index, layer) => layers.insert(*index, layer.clone()),
+			MapEdit::ModifyLayer { index, from, .. } => layers[*index].set_settings(from),
+		}
 	}
 }
 
 #[derive(Debug)]
 pub struct MapInfoPopup {
+	/// The unit's name before it started being edited.
 	pub unit: String,
 	pub position: (u32, u32),
 }
@@ -51,3 +1264,127 @@ impl MapInfoPopup {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn rect_from_corners_in_any_order() {
+		let rect = TileRect {
+			x: 2,
+			y: 1,
+			width: 3,
+			height: 4,
+		};
+		assert_eq!(TileRect::from_corners((2, 1), (4, 4)), rect);
+		assert_eq!(TileRect::from_corners((4, 4), (2, 1)), rect);
+		assert_eq!(TileRect::from_corners((2, 4), (4, 1)), rect);
+	}
+
+	#[test]
+	fn rect_contains_its_edges_only() {
+		let rect = TileRect::from_corners((2, 1), (4, 4));
+		assert!(rect.contains(2, 1));
+		assert!(rect.contains(4, 4));
+		assert!(!rect.contains(1, 1));
+		assert!(!rect.contains(5, 4));
+		assert!(!rect.contains(4, 5));
+	}
+
+	/// Builds a map from rows of ground tiles.
+	fn map_from_rows(rows: &[&[usize]]) -> MapEditor {
+		let mut map = MapEditor::with_size(String::from("test"), rows[0].len(), rows.len());
+		map.data.data = rows.concat();
+		map
+	}
+
+	#[test]
+	fn flood_fill_stops_at_other_tiles() {
+		let mut map = map_from_rows(&[&[0, 0, 1, 0], &[1, 0, 1, 0], &[0, 0, 1, 0]]);
+		map.flood_fill(0, 0, 2);
+		assert_eq!(map.data.data, [2, 2, 1, 0, 1, 2, 1, 0, 2, 2, 1, 0]);
+	}
+
+	#[test]
+	fn flood_fill_is_undone_at_once() {
+		let mut map = map_from_rows(&[&[0, 0], &[0, 0]]);
+		map.flood_fill(1, 1, 3);
+		assert_eq!(map.data.data, [3; 4]);
+
+		map.undo();
+		assert_eq!(map.data.data, [0; 4]);
+		assert!(!map.history.can_undo());
+	}
+
+	#[test]
+	fn flood_fill_skips_locked_layers() {
+		let mut map = map_from_rows(&[&[0, 0], &[0, 0]]);
+		map.layers[0].locked = true;
+		map.flood_fill(0, 0, 1);
+		assert_eq!(map.data.data, [0; 4]);
+		assert!(!map.history.can_undo());
+	}
+
+	#[test]
+	fn region_survives_toml() {
+		let mut map = map_from_rows(&[&[0, 1, 2], &[3, 4, 5]]);
+		map.add_layer();
+		*map.get_tile(1, 1, 0) = 7;
+		let mut unit = Unit::new(2, 1, Uuid::new_v4(), "Player");
+		unit.name = String::from("Guard");
+		let item = Uuid::new_v4();
+		unit.items.push(item);
+		map.units.push(unit);
+		map.data.spawns.push((0, 1));
+
+		let region = map.to_region();
+		let parsed = MapRegion::from_toml(&region.to_toml().unwrap(), &Factions::new()).unwrap();
+		assert_eq!((parsed.width, parsed.height), (3, 2));
+		assert_eq!(parsed.layers, region.layers);
+		assert_eq!(parsed.layers[1][1], 7);
+		assert_eq!(parsed.layers[1][0], EMPTY_TILE);
+		assert_eq!(parsed.spawns, [(0, 1)]);
+		assert_eq!(parsed.units.len(), 1);
+		assert_eq!(parsed.units[0].class, region.units[0].class);
+		assert_eq!(parsed.units[0].name, "Guard");
+		assert_eq!(parsed.units[0].items, [item]);
+	}
+
+	#[test]
+	fn oversized_region_is_an_error() {
+		let text = format!(
+			"[region]\nwidth = {}\nheight = 2\nlayers = [[0]]\nunits = []\nspawns = []\n",
+			i64::MAX
+		);
+		assert!(MapRegion::from_toml(&text, &Factions::new()).is_err());
+	}
+
+	#[test]
+	fn anchor_offsets_by_half_or_all_of_the_change() {
+		let from = (10, 10);
+		let to = (14, 7);
+		assert_eq!(Anchor { x: 0, y: 0 }.offset(from, to), (0, 0));
+		assert_eq!(Anchor { x: 1, y: 1 }.offset(from, to), (2, -1));
+		assert_eq!(Anchor { x: 2, y: 2 }.offset(from, to), (4, -3));
+	}
+
+	#[test]
+	fn transform_moves_contents_and_reports_losses() {
+		let mut map = map_from_rows(&[&[1, 2], &[3, 4]]);
+		map.units.push(Unit::new(0, 0, Uuid::new_v4(), "Player"));
+		map.units.push(Unit::new(1, 1, Uuid::new_v4(), "Player"));
+		map.data.spawns.push((0, 1));
+
+		let region = map.transformed(2, 3, (1, 1), 9);
+		assert_eq!(region.layers[0], [9, 9, 9, 1, 9, 3]);
+		assert_eq!(region.units.len(), 1);
+		assert_eq!((region.units[0].x, region.units[0].y), (1, 1));
+		assert_eq!(region.spawns, [(1, 2)]);
+
+		let (units, spawns) = map.lost_contents(2, 3, (1, 1));
+		assert_eq!(units.len(), 1);
+		assert_eq!((units[0].x, units[0].y), (1, 1));
+		assert!(spawns.is_empty());
+	}
+}
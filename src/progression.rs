@@ -0,0 +1,261 @@
+use crate::toml_helpers::*;
+use crate::*;
+use fe_data::FeError;
+use std::collections::BTreeMap;
+use std::error::Error;
+use toml::*;
+use uuid::Uuid;
+
+/// A class which units can promote into.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct Promotion {
+	pub class: Uuid,
+	/// The level a unit must reach before it can promote.
+	pub level: u32,
+	/// The UUID of the item used to promote, if one is needed.
+	pub item: Option<Uuid>,
+}
+
+impl Promotion {
+	pub fn new(class: Uuid) -> Self {
+		Self {
+			class,
+			level: 10,
+			item: None,
+		}
+	}
+
+	fn from_table(table: &Table) -> Result<Self, Box<dyn Error>> {
+		let class = match Uuid::parse_str(&get_string(table, "class")?) {
+			Ok(class) => class,
+			Err(msg) => Err(FeError::from(format!(
+				"Promotion has an invalid class ID: {msg}"
+			)))?,
+		};
+		let mut promotion = Self::new(class);
+		if table.contains_key("level") {
+			promotion.level = get_usize(table, "level")?.clamp(1, MAX_LEVEL as usize) as u32;
+		}
+		if table.contains_key("item") {
+			match Uuid::parse_str(&get_string(table, "item")?) {
+				Ok(item) => promotion.item = Some(item),
+				Err(msg) => Err(FeError::from(format!(
+					"Promotion has an invalid item ID: {msg}"
+				)))?,
+			}
+		}
+		Ok(promotion)
+	}
+
+	fn to_table(&self) -> Table {
+		let mut table = Table::new();
+		table.insert("class".into(), Value::String(self.class.to_string()));
+		table.insert("level".into(), usize_value(self.level as usize));
+		if let Some(item) = self.item {
+			table.insert("item".into(), Value::String(item.to_string()));
+		}
+		table
+	}
+}
+
+/// How a class moves, which weapons it can use, and which classes it promotes into.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct ClassProgression {
+	/// The class's movement class, as listed in the terrain editor, or empty if it has none.
+	pub movement: String,
+	/// The weapon types the class can use, and the rank it starts with in each.
+	pub weapons: BTreeMap<WeaponType, WeaponRank>,
+	pub promotions: Vec<Promotion>,
+}
+
+impl ClassProgression {
+	pub fn new() -> Self {
+		Self {
+			movement: String::new(),
+			weapons: BTreeMap::new(),
+			promotions: Vec::new(),
+		}
+	}
+
+	/// Removes the progression keys from a class's table, so that the rest can be read as `ClassData`.
+	/// Classes saved before these were added can't use any weapons or promote.
+	pub fn take_from(table: &mut Table) -> Result<Self, Box<dyn Error>> {
+		let mut progression = Self::new();
+
+		match table.remove("movement") {
+			Some(Value::String(movement)) => progression.movement = movement,
+			Some(_) => Err(FeError::from("movement is not a string"))?,
+			None => {}
+		}
+
+		match table.remove("weapons") {
+			Some(Value::Table(weapons)) => {
+				for (weapon_type, rank) in weapons {
+					let Some(weapon_type) = WeaponType::from_name(&weapon_type) else {
+						Err(FeError::from(format!(
+							"Unknown weapon type \"{weapon_type}\""
+						)))?
+					};
+					let Some(rank) = rank.as_str().and_then(WeaponRank::from_name) else {
+						Err(FeError::from(format!(
+							"{} rank must be one of E, D, C, B, A or S",
+							weapon_type.name()
+						)))?
+					};
+					progression.weapons.insert(weapon_type, rank);
+				}
+			}
+			Some(_) => Err(FeError::from("weapons is not a table"))?,
+			None => {}
+		}
+
+		match table.remove("promotion") {
+			Some(Value::Array(promotions)) => {
+				for promotion in promotions {
+					let Value::Table(promotion) = promotion else {
+						Err(FeError::from("Promotion is not a table"))?
+					};
+					progression
+						.promotions
+						.push(Promotion::from_table(&promotion)?);
+				}
+			}
+			Some(_) => Err(FeError::from("promotion is not an array"))?,
+			None => {}
+		}
+
+		Ok(progression)
+	}
+
+	pub fn write_to(&self, table: &mut Table) {
+		if !self.movement.is_empty() {
+			table.insert("movement".into(), Value::String(self.movement.clone()));
+		}
+		table.insert(
+			"weapons".into(),
+			Value::Table(
+				self.weapons
+					.iter()
+					.map(|(weapon_type, rank)| {
+						(weapon_type.name().into(), Value::String(rank.name().into()))
+					})
+					.collect(),
+			),
+		);
+		table.insert(
+			"promotion".into(),
+			Value::Array(
+				self.promotions
+					.iter()
+					.map(|p| Value::Table(p.to_table()))
+					.collect(),
+			),
+		);
+	}
+}
+
+/// Returns the classes which can eventually promote back into themselves.
+pub fn promotion_cycles(classes: &[ClassEntry]) -> Vec<Uuid> {
+	let targets = |uuid: Uuid| {
+		classes
+			.iter()
+			.find(|c| c.uuid == uuid)
+			.into_iter()
+			.flat_map(|c| c.progression.promotions.iter().map(|p| p.class))
+	};
+
+	let mut cycles = Vec::new();
+	for class in classes {
+		let mut visited = Vec::new();
+		let mut stack: Vec<Uuid> = targets(class.uuid).collect();
+		while let Some(uuid) = stack.pop() {
+			if uuid == class.uuid {
+				cycles.push(class.uuid);
+				break;
+			}
+			if !visited.contains(&uuid) {
+				visited.push(uuid);
+				stack.extend(targets(uuid));
+			}
+		}
+	}
+	cycles
+}
+
+/// Returns how many promotions it takes to reach each class from one which isn't promoted into,
+/// such as 1 for a class which a starting class promotes into.
+/// Classes in a cycle are given a depth no greater than the number of classes.
+pub fn promotion_depths(classes: &[ClassEntry]) -> Vec<usize> {
+	let mut depths = vec![0; classes.len()];
+	// Each pass pushes depths one promotion further, so no more passes than classes are needed.
+	for _ in 0..classes.len() {
+		let mut changed = false;
+		for (i, class) in classes.iter().enumerate() {
+			for promotion in &class.progression.promotions {
+				let Some(target) = classes.iter().position(|c| c.uuid == promotion.class) else {
+					continue;
+				};
+				let depth = (depths[i] + 1).min(classes.len());
+				if depth > depths[target] {
+					depths[target] = depth;
+					changed = true;
+				}
+			}
+		}
+		if !changed {
+			break;
+		}
+	}
+	depths
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::path::PathBuf;
+
+	/// Creates a class for each entry, promoting into the classes at the listed indices.
+	fn classes_from_promotions(promotions: &[&[usize]]) -> Vec<ClassEntry> {
+		let mut classes: Vec<ClassEntry> = promotions
+			.iter()
+			.map(|_| ClassEntry::with_texture(PathBuf::new()))
+			.collect();
+		for (i, targets) in promotions.iter().enumerate() {
+			for &target in *targets {
+				let promotion = Promotion::new(classes[target].uuid);
+				classes[i].progression.promotions.push(promotion);
+			}
+		}
+		classes
+	}
+
+	#[test]
+	fn promotion_chain_has_no_cycles() {
+		let classes = classes_from_promotions(&[&[1, 2], &[2], &[]]);
+		assert!(promotion_cycles(&classes).is_empty());
+		assert_eq!(promotion_depths(&classes), [0, 1, 2]);
+	}
+
+	#[test]
+	fn promotion_cycles_are_found() {
+		// 0 promotes into a cycle between 1 and 2, and 3 promotes into itself.
+		let classes = classes_from_promotions(&[&[1], &[2], &[1], &[3]]);
+		let cycles = promotion_cycles(&classes);
+		assert_eq!(cycles, [classes[1].uuid, classes[2].uuid, classes[3].uuid]);
+		// Depths in a cycle stop growing once they reach the number of classes.
+		let depths = promotion_depths(&classes);
+		assert_eq!(depths[0], 0);
+		assert!(depths.iter().all(|&d| d <= classes.len()));
+	}
+
+	#[test]
+	fn promotions_to_missing_classes_are_ignored() {
+		let mut classes = classes_from_promotions(&[&[], &[]]);
+		classes[0]
+			.progression
+			.promotions
+			.push(Promotion::new(Uuid::new_v4()));
+		assert!(promotion_cycles(&classes).is_empty());
+		assert_eq!(promotion_depths(&classes), [0, 0]);
+	}
+}
@@ -0,0 +1,195 @@
+use crate::toml_helpers::*;
+use fe_data::FeError;
+use std::error::Error;
+use toml::*;
+
+pub const STAT_COUNT: usize = 9;
+pub const STAT_NAMES: [&str; STAT_COUNT] =
+	["HP", "Str", "Mag", "Skl", "Spd", "Lck", "Def", "Res", "Mov"];
+/// The keys used for each stat in TOML.
+const STAT_KEYS: [&str; STAT_COUNT] =
+	["hp", "str", "mag", "skl", "spd", "lck", "def", "res", "mov"];
+
+pub const MAX_STAT: i32 = 255;
+pub const MAX_GROWTH: i32 = 100;
+pub const MAX_LEVEL: u32 = 99;
+
+/// Reads a single stat, which must be no greater than `MAX_STAT`.
+fn get_stat(table: &Table, key: &str) -> Result<i32, Box<dyn Error>> {
+	let stat = get_usize(table, key)?;
+	if stat > MAX_STAT as usize {
+		Err(FeError::from(format!(
+			"{key} ({stat}) must be no greater than {MAX_STAT}"
+		)))?;
+	}
+	Ok(stat as i32)
+}
+
+/// One value for each stat, in the same order as `STAT_NAMES`.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct Stats(pub [i32; STAT_COUNT]);
+
+impl Stats {
+	pub fn from_table(table: &Table) -> Result<Self, Box<dyn Error>> {
+		let mut stats = [0; STAT_COUNT];
+		for (stat, key) in stats.iter_mut().zip(STAT_KEYS) {
+			if table.contains_key(key) {
+				*stat = get_stat(table, key)?;
+			}
+		}
+		Ok(Self(stats))
+	}
+
+	pub fn to_table(&self) -> Table {
+		STAT_KEYS
+			.iter()
+			.zip(self.0)
+			.map(|(key, stat)| (key.to_string(), Value::Integer(stat as i64)))
+			.collect()
+	}
+}
+
+/// Stats which replace some of a class's, such as for a unit placed on a map.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub struct StatOverrides(pub [Option<i32>; STAT_COUNT]);
+
+impl StatOverrides {
+	/// Reads overrides from a table. Stats which aren't in it aren't overridden.
+	pub fn from_table(table: &Table) -> Result<Self, Box<dyn Error>> {
+		let mut stats = [None; STAT_COUNT];
+		for (stat, key) in stats.iter_mut().zip(STAT_KEYS) {
+			if table.contains_key(key) {
+				*stat = Some(get_usize(table, key)?.min(MAX_STAT as usize) as i32);
+			}
+		}
+		Ok(Self(stats))
+	}
+
+	pub fn to_table(&self) -> Table {
+		STAT_KEYS
+			.iter()
+			.zip(self.0)
+			.filter_map(|(key, stat)| Some((key.to_string(), Value::Integer(stat? as i64))))
+			.collect()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.0.iter().all(|stat| stat.is_none())
+	}
+
+	/// Returns `stats` with each overridden stat replaced.
+	pub fn apply(&self, stats: &Stats) -> Stats {
+		let mut stats = *stats;
+		for (stat, stat_override) in stats.0.iter_mut().zip(self.0) {
+			if let Some(value) = stat_override {
+				*stat = value;
+			}
+		}
+		stats
+	}
+}
+
+/// A class's base stats, growth rates (as percentages), and stat caps.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct ClassStats {
+	pub bases: Stats,
+	pub growths: Stats,
+	pub caps: Stats,
+}
+
+impl ClassStats {
+	pub fn new() -> Self {
+		Self {
+			bases: Stats([18, 5, 0, 5, 5, 0, 5, 0, 5]),
+			growths: Stats([0; STAT_COUNT]),
+			caps: Stats([60, 30, 30, 30, 30, 30, 30, 30, 15]),
+		}
+	}
+
+	/// Removes the stat tables from a class's table, so that the rest can be read as `ClassData`.
+	/// Classes saved before stats were added use the default stats.
+	pub fn take_from(table: &mut Table) -> Result<Self, Box<dyn Error>> {
+		let mut stats = Self::new();
+		for (key, stats) in [
+			("bases", &mut stats.bases),
+			("growths", &mut stats.growths),
+			("caps", &mut stats.caps),
+		] {
+			match table.remove(key) {
+				Some(Value::Table(table)) => *stats = Stats::from_table(&table)?,
+				Some(_) => Err(FeError::from(format!("{key} is not a table")))?,
+				None => {}
+			}
+		}
+		Ok(stats)
+	}
+
+	pub fn write_to(&self, table: &mut Table) {
+		table.insert("bases".into(), Value::Table(self.bases.to_table()));
+		table.insert("growths".into(), Value::Table(self.growths.to_table()));
+		table.insert("caps".into(), Value::Table(self.caps.to_table()));
+	}
+
+	/// Describes the first problem with these stats, if there is one.
+	pub fn validate(&self) -> Result<(), String> {
+		for (i, name) in STAT_NAMES.iter().enumerate() {
+			let (base, growth, cap) = (self.bases.0[i], self.growths.0[i], self.caps.0[i]);
+			if !(0..=MAX_STAT).contains(&cap) {
+				return Err(format!(
+					"{name} cap ({cap}) must be between 0 and {MAX_STAT}"
+				));
+			}
+			if !(0..=cap).contains(&base) {
+				return Err(format!(
+					"{name} base ({base}) must be between 0 and its cap ({cap})"
+				));
+			}
+			if !(0..=MAX_GROWTH).contains(&growth) {
+				return Err(format!(
+					"{name} growth ({growth}%) must be between 0% and {MAX_GROWTH}%"
+				));
+			}
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn overrides_round_trip() {
+		let mut overrides = StatOverrides::default();
+		overrides.0[0] = Some(30);
+		overrides.0[8] = Some(0);
+		let table = overrides.to_table();
+		assert_eq!(table.len(), 2);
+		assert_eq!(StatOverrides::from_table(&table).unwrap(), overrides);
+	}
+
+	#[test]
+	fn overrides_are_clamped() {
+		let table: Table = "str = 300".parse().unwrap();
+		let overrides = StatOverrides::from_table(&table).unwrap();
+		assert_eq!(overrides.0[1], Some(MAX_STAT));
+	}
+
+	#[test]
+	fn empty_overrides() {
+		let overrides = StatOverrides::from_table(&Table::new()).unwrap();
+		assert!(overrides.is_empty());
+		assert!(overrides.to_table().is_empty());
+		let stats = ClassStats::new().bases;
+		assert_eq!(overrides.apply(&stats), stats);
+	}
+
+	#[test]
+	fn apply_replaces_only_overridden_stats() {
+		let mut overrides = StatOverrides::default();
+		overrides.0[1] = Some(12);
+		assert!(!overrides.is_empty());
+		let stats = overrides.apply(&Stats([18, 5, 0, 5, 5, 0, 5, 0, 5]));
+		assert_eq!(stats, Stats([18, 12, 0, 5, 5, 0, 5, 0, 5]));
+	}
+}
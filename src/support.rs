@@ -1,10 +1,10 @@
 use glium::glutin;
 use glium::glutin::event::{Event, WindowEvent};
 use glium::glutin::event_loop::{ControlFlow, EventLoop};
-use glium::glutin::window::WindowBuilder;
+use glium::glutin::window::{Window, WindowBuilder};
 use glium::{Display, Surface};
-use imgui::{Context, FontConfig, FontSource, Ui};
-use imgui_glium_renderer::Renderer;
+use imgui::{Context, FontConfig, FontSource, Textures, Ui};
+use imgui_glium_renderer::{Renderer, Texture};
 use imgui_winit_support::{HiDpiMode, WinitPlatform};
 use std::path::Path;
 use std::time::Instant;
@@ -117,7 +117,16 @@ pub fn init(title: &str) -> System {
 }
 
 impl System {
-	pub fn main_loop<F: FnMut(&mut bool, &mut Ui) + 'static>(self, mut run_ui: F) {
+	/// Runs `run_ui` once per frame.
+	/// It's given the window so that it can change things like the title,
+	/// and the display and textures so that it can load new textures.
+	///
+	/// The `run` flag starts each frame as false if the user tried to close the window.
+	/// The program exits if it's false once `run_ui` returns, so setting it to true cancels closing.
+	pub fn main_loop<F>(self, mut run_ui: F)
+	where
+		F: FnMut(&mut bool, &mut Ui, &Window, &Display, &mut Textures<Texture>) + 'static,
+	{
 		let System {
 			event_loop,
 			display,
@@ -127,6 +136,7 @@ impl System {
 			..
 		} = self;
 		let mut last_frame = Instant::now();
+		let mut close_requested = false;
 
 		event_loop.run(move |event, _, control_flow| match event {
 			Event::NewEvents(_) => {
@@ -143,14 +153,21 @@ impl System {
 			}
 			Event::RedrawRequested(_) => {
 				let ui = imgui.frame();
+				let gl_window = display.gl_window();
 
-				let mut run = true;
-				run_ui(&mut run, ui);
+				let mut run = !close_requested;
+				close_requested = false;
+				run_ui(
+					&mut run,
+					ui,
+					gl_window.window(),
+					&display,
+					renderer.textures(),
+				);
 				if !run {
 					*control_flow = ControlFlow::Exit;
 				}
 
-				let gl_window = display.gl_window();
 				let mut target = display.draw();
 				target.clear_color_srgb(0.0, 0.0, 0.0, 1.0);
 				platform.prepare_render(ui, gl_window.window());
@@ -163,7 +180,7 @@ impl System {
 			Event::WindowEvent {
 				event: WindowEvent::CloseRequested,
 				..
-			} => *control_flow = ControlFlow::Exit,
+			} => close_requested = true,
 			event => {
 				let gl_window = display.gl_window();
 				platform.handle_event(imgui.io_mut(), gl_window.window(), &event);
@@ -0,0 +1,654 @@
+use crate::toml_helpers::*;
+use crate::*;
+use fe_data::FeError;
+use imgui::*;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use toml::*;
+use uuid::Uuid;
+
+/// The movement classes given to new projects.
+const DEFAULT_MOVEMENT_CLASSES: [&str; 4] = ["Infantry", "Armored", "Cavalry", "Flying"];
+
+/// What a tile means to units standing on or moving through it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TerrainType {
+	pub name: String,
+	pub uuid: Uuid,
+	/// The cost of entering this terrain for each movement class.
+	/// Movement classes which are missing can't enter the terrain at all.
+	pub move_costs: BTreeMap<String, u32>,
+	pub defense: i32,
+	pub avoid: i32,
+	/// The percentage of a unit's HP restored at the start of each turn.
+	pub heal: i32,
+}
+
+impl TerrainType {
+	pub fn new(movement_classes: &[String]) -> Self {
+		Self {
+			name: String::new(),
+			uuid: Uuid::new_v4(),
+			move_costs: movement_classes.iter().map(|c| (c.clone(), 1)).collect(),
+			defense: 0,
+			avoid: 0,
+			heal: 0,
+		}
+	}
+
+	pub fn from_table(name: String, table: &Table) -> Result<Self, Box<dyn Error>> {
+		let get_int = |key: &str| -> Result<i32, Box<dyn Error>> {
+			match table.get(key) {
+				Some(Value::Integer(i)) => match i32::try_from(*i) {
+					Ok(i) => Ok(i),
+					Err(_) => Err(FeError::from(format!(
+						"{name}: {key} ({i}) is out of range"
+					)))?,
+				},
+				Some(_) => Err(FeError::from(format!("{name}: {key} is not an integer")))?,
+				None => Ok(0),
+			}
+		};
+
+		let mut move_costs = BTreeMap::new();
+		if table.contains_key("cost") {
+			for (class, cost) in get_table(table, "cost")? {
+				let cost = u32::try_from(as_usize(cost, class)?).map_err(|_| {
+					FeError::from(format!("{name}: the cost for {class} is too large"))
+				})?;
+				move_costs.insert(class.clone(), cost);
+			}
+		}
+
+		Ok(Self {
+			uuid: Uuid::new_v4(),
+			move_costs,
+			defense: get_int("defense")?,
+			avoid: get_int("avoid")?,
+			heal: get_int("heal")?,
+			name,
+		})
+	}
+
+	pub fn to_table(&self) -> Table {
+		let mut table = Table::new();
+		table.insert("defense".into(), Value::Integer(self.defense as i64));
+		table.insert("avoid".into(), Value::Integer(self.avoid as i64));
+		table.insert("heal".into(), Value::Integer(self.heal as i64));
+		table.insert(
+			"cost".into(),
+			Value::Table(
+				self.move_costs
+					.iter()
+					.map(|(class, cost)| (class.clone(), Value::Integer(*cost as i64)))
+					.collect(),
+			),
+		);
+		table
+	}
+
+	/// Draws the terrain's fields.
+	/// Returns the terrain's previous state if an edit was completed this frame.
+	fn editor(
+		&mut self,
+		ui: &Ui,
+		movement_classes: &[String],
+		edit_start: &mut Option<TerrainType>,
+	) -> Option<TerrainType> {
+		let before = self.clone();
+		let mut edit = None;
+
+		ui.input_text("##name", &mut self.name).hint("Name").build();
+		edit = edit.or(track_edit(ui, &before, edit_start));
+
+		ui.input_int("Defense", &mut self.defense).build();
+		edit = edit.or(track_edit(ui, &before, edit_start));
+		ui.input_int("Avoid", &mut self.avoid).build();
+		edit = edit.or(track_edit(ui, &before, edit_start));
+		ui.input_int("Heal %", &mut self.heal).build();
+		edit = edit.or(track_edit(ui, &before, edit_start));
+		self.heal = self.heal.clamp(0, 100);
+
+		ui.text("Movement cost:");
+		ui.hover_tooltip("Unchecked movement classes can't enter this terrain.");
+		for class in movement_classes {
+			let _id = ui.push_id(class);
+			let mut passable = self.move_costs.contains_key(class);
+			if ui.checkbox("##passable", &mut passable) {
+				edit = Some(before.clone());
+				if passable {
+					self.move_costs.insert(class.clone(), 1);
+				} else {
+					self.move_costs.remove(class);
+				}
+			}
+			ui.same_line();
+			if let Some(cost) = self.move_costs.get_mut(class) {
+				let mut value = *cost as i32;
+				ui.input_int(class, &mut value).build();
+				*cost = value.max(1) as u32;
+				edit = edit.or(track_edit(ui, &before, edit_start));
+			} else {
+				ui.text(class);
+			}
+		}
+
+		edit
+	}
+}
+
+/// A reversible change to the terrain editor.
+/// Terrain types keep their UUID when they are restored, so tiles assigned to them are too.
+#[derive(Debug)]
+pub enum TerrainEdit {
+	Insert(usize, TerrainType),
+	/// Also records the tiles which used the terrain type.
+	Remove(usize, TerrainType, Vec<usize>),
+	Modify {
+		index: usize,
+		from: TerrainType,
+		to: TerrainType,
+	},
+	AssignTile {
+		tile: usize,
+		from: Option<Uuid>,
+		to: Option<Uuid>,
+	},
+	/// A change to the list of movement classes, which also changes every terrain's costs.
+	MovementClasses {
+		from: Vec<String>,
+		to: Vec<String>,
+		terrain_from: Vec<TerrainType>,
+		terrain_to: Vec<TerrainType>,
+	},
+}
+
+impl TerrainEdit {
+	pub fn apply(&self, data: &mut TerrainData) {
+		match self {
+			TerrainEdit::Insert(index, terrain) => data.terrain.insert(*index, terrain.clone()),
+			TerrainEdit::Remove(index, _, tiles) => {
+				data.terrain.remove(*index);
+				for tile in tiles {
+					data.tiles[*tile] = None;
+				}
+			}
+			TerrainEdit::Modify { index, to, .. } => data.terrain[*index] = to.clone(),
+			TerrainEdit::AssignTile { tile, to, .. } => data.tiles[*tile] = *to,
+			TerrainEdit::MovementClasses { to, terrain_to, .. } => {
+				data.movement_classes = to.clone();
+				data.terrain = terrain_to.clone();
+			}
+		}
+	}
+
+	pub fn revert(&self, data: &mut TerrainData) {
+		match self {
+			TerrainEdit::Insert(index, _) => {
+				data.terrain.remove(*index);
+			}
+			TerrainEdit::Remove(index, terrain, tiles) => {
+				data.terrain.insert(*index, terrain.clone());
+				for tile in tiles {
+					data.tiles[*tile] = Some(terrain.uuid);
+				}
+			}
+			TerrainEdit::Modify { index, from, .. } => data.terrain[*index] = from.clone(),
+			TerrainEdit::AssignTile { tile, from, .. } => data.tiles[*tile] = *from,
+			TerrainEdit::MovementClasses {
+				from, terrain_from, ..
+			} => {
+				data.movement_classes = from.clone();
+				data.terrain = terrain_from.clone();
+			}
+		}
+	}
+}
+
+/// Everything saved in `terrain.toml`.
+#[derive(Debug)]
+pub struct TerrainData {
+	pub movement_classes: Vec<String>,
+	pub terrain: Vec<TerrainType>,
+	/// The terrain type of each tile in the tileset, if it has been assigned one.
+	pub tiles: Vec<Option<Uuid>>,
+}
+
+impl TerrainData {
+	/// Looks up a terrain type by its UUID.
+	pub fn get(&self, uuid: Option<Uuid>) -> Option<&TerrainType> {
+		let uuid = uuid?;
+		self.terrain.iter().find(|t| t.uuid == uuid)
+	}
+
+	/// Returns the terrain type of a tile in the tileset, if it has one.
+	pub fn of_tile(&self, tile: usize) -> Option<&TerrainType> {
+		self.get(*self.tiles.get(tile)?)
+	}
+}
+
+pub struct TerrainEditor {
+	pub save_state: SaveState,
+	pub data: TerrainData,
+	pub history: History<TerrainEdit>,
+	/// Whether the terrain window is focused this frame; used to direct undo and redo.
+	pub focused: bool,
+	/// The terrain's state when the current edit began.
+	edit_start: Option<TerrainType>,
+	/// The movement classes when the current rename began.
+	rename_start: Option<Vec<String>>,
+}
+
+impl TerrainEditor {
+	/// Opens a terrain file, or creates an empty set of terrain if it doesn't exist.
+	/// `tile_count` is the number of tiles in the tileset.
+	pub fn open(path: impl AsRef<Path>, tile_count: usize) -> Result<Self, Box<dyn Error>> {
+		let mut movement_classes: Vec<String> = DEFAULT_MOVEMENT_CLASSES
+			.iter()
+			.map(|c| c.to_string())
+			.collect();
+		let mut terrain = Vec::new();
+		let mut tiles = vec![None; tile_count];
+
+		if let Ok(toml) = fs::read_to_string(path) {
+			let toml: Table = toml.parse()?;
+
+			movement_classes.clear();
+			for class in get_array(&toml, "movement_classes")? {
+				let Value::String(class) = class else {
+					Err(FeError::from("Movement class is not a string"))?
+				};
+				movement_classes.push(class.clone());
+			}
+
+			if toml.contains_key("terrain") {
+				for (name, table) in get_table(&toml, "terrain")? {
+					let Value::Table(table) = table else {
+						Err(FeError::from("Terrain data is not a table"))?
+					};
+					terrain.push(TerrainType::from_table(name.clone(), table)?);
+				}
+			}
+
+			for (tile, name) in get_array(&toml, "tiles")?.iter().enumerate() {
+				let Value::String(name) = name else {
+					Err(FeError::from(format!(
+						"Terrain of tile {tile} is not a string"
+					)))?
+				};
+				// Tiles past the end of the tileset are dropped.
+				if tile >= tile_count || name.is_empty() {
+					continue;
+				}
+				match terrain.iter().find(|t| t.name == *name) {
+					Some(terrain) => tiles[tile] = Some(terrain.uuid),
+					None => Err(FeError::from(format!(
+						"Tile {tile} refers to an unknown terrain type: \"{name}\""
+					)))?,
+				}
+			}
+		}
+
+		Ok(Self {
+			save_state: SaveState::new(),
+			data: TerrainData {
+				movement_classes,
+				terrain,
+				tiles,
+			},
+			history: History::new(),
+			focused: false,
+			edit_start: None,
+			rename_start: None,
+		})
+	}
+
+	pub fn to_toml(&self) -> Result<String, Box<dyn Error>> {
+		let mut terrain = Table::new();
+		for i in &self.data.terrain {
+			if i.name.is_empty() {
+				Err(FeError::from("Terrain types must have a name"))?;
+			}
+			if terrain
+				.insert(i.name.clone(), Value::Table(i.to_table()))
+				.is_some()
+			{
+				Err(FeError::from(format!(
+					"More than one terrain type is named \"{}\"",
+					i.name
+				)))?;
+			}
+		}
+
+		let tiles = self
+			.data
+			.tiles
+			.iter()
+			.map(|tile| {
+				Value::String(match self.data.get(*tile) {
+					Some(terrain) => terrain.name.clone(),
+					None => String::new(),
+				})
+			})
+			.collect();
+
+		let mut toml = Table::new();
+		toml.insert(
+			"movement_classes".into(),
+			Value::Array(
+				self.data
+					.movement_classes
+					.iter()
+					.map(|c| Value::String(c.clone()))
+					.collect(),
+			),
+		);
+		toml.insert("tiles".into(), Value::Array(tiles));
+		toml.insert("terrain".into(), Value::Table(terrain));
+		Ok(toml::to_string(&toml)?)
+	}
+
+	fn push(&mut self, edit: TerrainEdit) {
+		self.history.push(edit);
+		self.save_state.sync_with(&self.history);
+	}
+
+	fn apply(&mut self, edit: TerrainEdit) {
+		edit.apply(&mut self.data);
+		self.push(edit);
+	}
+
+	pub fn undo(&mut self) {
+		self.edit_start = None;
+		self.rename_start = None;
+		if let Some(edit) = self.history.undo() {
+			edit.revert(&mut self.data);
+			self.save_state.sync_with(&self.history);
+		}
+	}
+
+	pub fn redo(&mut self) {
+		self.edit_start = None;
+		self.rename_start = None;
+		if let Some(edit) = self.history.redo() {
+			edit.apply(&mut self.data);
+			self.save_state.sync_with(&self.history);
+		}
+	}
+
+	pub fn mark_saved(&mut self) {
+		self.save_state.mark_saved();
+		self.history.mark_saved();
+	}
+
+	/// Makes room for every tile after the tileset is reloaded.
+	/// Tiles are never removed, so that shrinking the tileset by mistake doesn't lose their terrain.
+	pub fn grow_tiles(&mut self, tile_count: usize) {
+		if self.data.tiles.len() < tile_count {
+			self.data.tiles.resize(tile_count, None);
+		}
+	}
+
+	pub fn draw(&mut self, ui: &Ui, position: (f32, f32), texture_atlas: &TileAtlas) {
+		self.focused = false;
+		ui.window("Terrain")
+			.position([position.0, position.1], Condition::FirstUseEver)
+			.size([250.0, 400.0], Condition::FirstUseEver)
+			.focus_on_appearing(false)
+			.collapsed(true, Condition::FirstUseEver)
+			.unsaved_document(!self.save_state.is_saved())
+			.build(|| {
+				self.focused =
+					ui.is_window_focused_with_flags(WindowFocusedFlags::ROOT_AND_CHILD_WINDOWS);
+
+				if ui.collapsing_header("Movement Classes", TreeNodeFlags::empty()) {
+					self.movement_class_list(ui);
+				}
+
+				if ui.collapsing_header("Terrain Types", TreeNodeFlags::DEFAULT_OPEN) {
+					self.terrain_list(ui);
+				}
+
+				if ui.collapsing_header("Tiles", TreeNodeFlags::empty()) {
+					self.tile_list(ui, texture_atlas);
+				}
+			});
+	}
+
+	fn movement_class_list(&mut self, ui: &Ui) {
+		let before = self.data.movement_classes.clone();
+		let mut remove = None;
+
+		for i in 0..self.data.movement_classes.len() {
+			let _id = ui.push_id_usize(i);
+			ui.input_text("##name", &mut self.data.movement_classes[i])
+				.hint("Name")
+				.build();
+			if let Some(from) = track_edit(ui, &before, &mut self.rename_start) {
+				self.rename_movement_class(from, i);
+			}
+			ui.same_line();
+			if ui.button("Delete") {
+				remove = Some(i);
+			}
+		}
+
+		if let Some(index) = remove {
+			let mut to = self.data.movement_classes.clone();
+			let class = to.remove(index);
+			let mut terrain_to = self.data.terrain.clone();
+			for terrain in &mut terrain_to {
+				terrain.move_costs.remove(&class);
+			}
+			self.apply(TerrainEdit::MovementClasses {
+				from: self.data.movement_classes.clone(),
+				to,
+				terrain_from: self.data.terrain.clone(),
+				terrain_to,
+			});
+		}
+
+		if ui.button("Add movement class") {
+			let mut to = self.data.movement_classes.clone();
+			let mut name = String::from("New");
+			while to.contains(&name) {
+				name += "*";
+			}
+			to.push(name.clone());
+			let mut terrain_to = self.data.terrain.clone();
+			for terrain in &mut terrain_to {
+				terrain.move_costs.insert(name.clone(), 1);
+			}
+			self.apply(TerrainEdit::MovementClasses {
+				from: self.data.movement_classes.clone(),
+				to,
+				terrain_from: self.data.terrain.clone(),
+				terrain_to,
+			});
+		}
+	}
+
+	/// Moves every terrain's cost to the movement class's new name once it's done being edited.
+	/// Names which are empty or already in use are rejected.
+	fn rename_movement_class(&mut self, from: Vec<String>, index: usize) {
+		let old_name = &from[index];
+		let new_name = self.data.movement_classes[index].clone();
+		if new_name.is_empty() || from.contains(&new_name) {
+			self.data.movement_classes = from;
+			return;
+		}
+
+		let terrain_from = self.data.terrain.clone();
+		for terrain in &mut self.data.terrain {
+			if let Some(cost) = terrain.move_costs.remove(old_name) {
+				terrain.move_costs.insert(new_name.clone(), cost);
+			}
+		}
+		self.push(TerrainEdit::MovementClasses {
+			to: self.data.movement_classes.clone(),
+			from,
+			terrain_from,
+			terrain_to: self.data.terrain.clone(),
+		});
+	}
+
+	fn terrain_list(&mut self, ui: &Ui) {
+		let mut edits = Vec::new();
+
+		for (index, terrain) in self.data.terrain.iter_mut().enumerate() {
+			let _id = ui.push_id(terrain.uuid.to_string());
+			let label = if terrain.name.is_empty() {
+				String::from("New terrain")
+			} else {
+				terrain.name.clone()
+			};
+			ui.tree_node_config("##header")
+				.label::<String, String>(label)
+				.framed(true)
+				.opened(terrain.name.is_empty(), Condition::FirstUseEver)
+				.build(|| {
+					if let Some(from) =
+						terrain.editor(ui, &self.data.movement_classes, &mut self.edit_start)
+					{
+						edits.push(TerrainEdit::Modify {
+							index,
+							from,
+							to: terrain.clone(),
+						});
+					}
+					if ui.button("Delete") {
+						ui.open_popup("Delete");
+					}
+
+					if ui.modal_popup_config("Delete").build(|| {
+						ui.text(format!(
+							"Do you really want to delete \"{}\"?",
+							terrain.name
+						));
+						if ui.button("Cancel") {
+							ui.close_current_popup();
+						}
+						ui.same_line();
+						if ui.button("Delete") {
+							ui.close_current_popup();
+							return true;
+						}
+						false
+					}) == Some(true)
+					{
+						let tiles = self
+							.data
+							.tiles
+							.iter()
+							.enumerate()
+							.filter(|(_, t)| **t == Some(terrain.uuid))
+							.map(|(i, _)| i)
+							.collect();
+						edits.push(TerrainEdit::Remove(index, terrain.clone(), tiles));
+					}
+				});
+			ui.separator();
+		}
+
+		for edit in edits {
+			// Modifications have already been made by the UI.
+			if let TerrainEdit::Remove(..) = edit {
+				self.apply(edit);
+			} else {
+				self.push(edit);
+			}
+		}
+
+		if ui.button("Create new terrain") {
+			let terrain = TerrainType::new(&self.data.movement_classes);
+			self.apply(TerrainEdit::Insert(self.data.terrain.len(), terrain));
+		}
+	}
+
+	fn tile_list(&mut self, ui: &Ui, texture_atlas: &TileAtlas) {
+		let mut names = vec![String::from("None")];
+		names.extend(self.data.terrain.iter().map(|t| t.name.clone()));
+
+		for (tile, texture) in texture_atlas.iter().enumerate() {
+			let _id = ui.push_id_usize(tile);
+			texture.image(ui, [32.0, 32.0]);
+			ui.same_line();
+
+			let from = self.data.tiles[tile];
+			let mut selected = match self.data.terrain.iter().position(|t| Some(t.uuid) == from) {
+				Some(i) => i + 1,
+				None => 0,
+			};
+			if ui.combo_simple_string("##terrain", &mut selected, &names) {
+				let to = selected.checked_sub(1).map(|i| self.data.terrain[i].uuid);
+				self.apply(TerrainEdit::AssignTile { tile, from, to });
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_helpers::TempFile;
+
+	fn terrain_named(name: &str, movement_classes: &[String]) -> TerrainType {
+		TerrainType {
+			name: String::from(name),
+			..TerrainType::new(movement_classes)
+		}
+	}
+
+	#[test]
+	fn terrain_survives_saving() {
+		let file = TempFile::new();
+		let mut editor = TerrainEditor::open(&file.0, 3).unwrap();
+		let classes = editor.data.movement_classes.clone();
+		let mut forest = terrain_named("Forest", &classes);
+		forest.defense = 1;
+		forest.avoid = 20;
+		forest.move_costs.insert(classes[0].clone(), 2);
+		forest.move_costs.remove(&classes[2]);
+		editor.data.tiles[1] = Some(forest.uuid);
+		editor.data.terrain.push(terrain_named("Plains", &classes));
+		editor.data.terrain.push(forest.clone());
+
+		fs::write(&file.0, editor.to_toml().unwrap()).unwrap();
+		let loaded = TerrainEditor::open(&file.0, 3).unwrap();
+		assert_eq!(loaded.data.movement_classes, classes);
+		let names: Vec<&str> = loaded
+			.data
+			.terrain
+			.iter()
+			.map(|t| t.name.as_str())
+			.collect();
+		assert_eq!(names, ["Forest", "Plains"]);
+		let loaded_forest = &loaded.data.terrain[0];
+		assert_eq!(loaded_forest.move_costs, forest.move_costs);
+		assert_eq!((loaded_forest.defense, loaded_forest.avoid), (1, 20));
+		// Tiles refer to terrain by name in the file, and by UUID in the editor.
+		assert_eq!(loaded.data.tiles, [None, Some(loaded_forest.uuid), None]);
+	}
+
+	#[test]
+	fn terrain_names_must_be_unique() {
+		let mut editor = TerrainEditor::open(&TempFile::new().0, 0).unwrap();
+		let classes = editor.data.movement_classes.clone();
+		editor.data.terrain.push(terrain_named("Plains", &classes));
+		editor.data.terrain.push(terrain_named("Plains", &classes));
+		assert!(editor.to_toml().is_err());
+	}
+
+	#[test]
+	fn out_of_range_terrain_is_an_error() {
+		let file = TempFile::new();
+		fs::write(
+			&file.0,
+			"movement_classes = []\ntiles = []\n[terrain.Wall]\ndefense = 3000000000\n",
+		)
+		.unwrap();
+		assert!(TerrainEditor::open(&file.0, 0).is_err());
+	}
+}
@@ -1,42 +1,329 @@
+use crate::toml_helpers::*;
 use fe_data::FeError;
 use glium::backend::Facade;
 use glium::texture::{ClientFormat, RawImage2d};
 use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, SamplerBehavior};
 use glium::Texture2d;
 use image::{GenericImageView, Pixel, Rgba};
-use imgui::{TextureId, Textures};
+use imgui::{DrawListMut, Image, TextureId, Textures, Ui};
 use imgui_glium_renderer::Texture;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::ops::Index;
+use std::path::{Path, PathBuf};
 use std::{borrow::Cow, error::Error, rc::Rc};
+use toml::Table;
 
-const TILE_SIZE: u32 = 16;
+/// The size of the missing texture placeholder.
+const PLACEHOLDER_SIZE: u32 = 16;
+
+/// How tiles are arranged in a tileset image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TilesetLayout {
+	pub tile_width: u32,
+	pub tile_height: u32,
+	/// Pixels between the edge of the image and the first row and column of tiles.
+	pub margin: u32,
+	/// Pixels between neighbouring tiles.
+	pub spacing: u32,
+}
+
+impl TilesetLayout {
+	pub fn new() -> Self {
+		Self {
+			tile_width: 16,
+			tile_height: 16,
+			margin: 0,
+			spacing: 0,
+		}
+	}
+
+	/// Reads a layout from a table, such as `[tileset]` in `fe-editor.toml`.
+	/// Missing keys keep their default values.
+	pub fn from_table(table: &Table) -> Result<Self, Box<dyn Error>> {
+		let mut layout = Self::new();
+		for (key, field) in [
+			("tile_width", &mut layout.tile_width),
+			("tile_height", &mut layout.tile_height),
+			("margin", &mut layout.margin),
+			("spacing", &mut layout.spacing),
+		] {
+			if table.contains_key(key) {
+				*field = u32::try_from(get_usize(table, key)?)
+					.map_err(|_| FeError::from(format!("{key} is too large")))?;
+			}
+		}
+		if layout.tile_width == 0 || layout.tile_height == 0 {
+			Err(FeError::from(
+				"tile_width and tile_height must be at least 1",
+			))?;
+		}
+		Ok(layout)
+	}
+
+	/// Returns how many tiles fit along one side of an image.
+	fn tiles_along(&self, side: &str, length: u32, tile: u32) -> Result<u32, Box<dyn Error>> {
+		let (margin, spacing) = (self.margin, self.spacing);
+		// The layout is read from a file, so it could be large enough to overflow.
+		let margins = margin
+			.checked_mul(2)
+			.ok_or_else(|| FeError::from(format!("Tileset margin ({margin}) is too large")))?;
+		let spacing_too_large =
+			|| FeError::from(format!("Tileset spacing ({spacing}) is too large"));
+		let step = tile.checked_add(spacing).ok_or_else(spacing_too_large)?;
+		let tiles = length
+			.checked_add(spacing)
+			.ok_or_else(spacing_too_large)?
+			.saturating_sub(margins)
+			/ step;
+		// The length taken up by some number of tiles, along with the margins and the spacing between them.
+		// It's widened to `u64`, which these can't overflow.
+		let length_of = |tiles: u32| {
+			margins as u64
+				+ tiles as u64 * tile as u64
+				+ tiles.saturating_sub(1) as u64 * spacing as u64
+		};
+		if tiles == 0 {
+			Err(FeError::from(format!(
+				"Tileset {side} ({length} pixels) is too small for a {tile} pixel tile \
+				with a margin of {margin}; it must be at least {} pixels.",
+				length_of(1)
+			)))?;
+		} else if length_of(tiles) != length as u64 {
+			Err(FeError::from(format!(
+				"Tileset {side} ({length} pixels) doesn't fit {tile} pixel tiles \
+				with a margin of {margin} and spacing of {spacing}. \
+				The closest {side}s that would are {} and {} pixels.",
+				length_of(tiles),
+				length_of(tiles.saturating_add(1))
+			)))?;
+		}
+		Ok(tiles)
+	}
+
+	/// Returns the number of columns and rows of tiles in an image.
+	pub fn grid_size<I: GenericImageView>(&self, image: &I) -> Result<(u32, u32), Box<dyn Error>> {
+		Ok((
+			self.tiles_along("width", image.width(), self.tile_width)?,
+			self.tiles_along("height", image.height(), self.tile_height)?,
+		))
+	}
+}
+
+/// A texture which couldn't be found while drawing.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MissingTexture {
+	Icon(PathBuf),
+	Tile(usize),
+}
+
+impl fmt::Display for MissingTexture {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Icon(path) => write!(f, "Icon \"{}\"", path.display()),
+			Self::Tile(tile) => write!(f, "Tile {tile}"),
+		}
+	}
+}
+
+/// Draws a placeholder in place of missing icons and tiles, and remembers which were missing,
+/// so that a renamed icon or a smaller tileset doesn't crash the editor.
+pub struct MissingTextures {
+	pub placeholder: TextureId,
+	missing: RefCell<BTreeSet<MissingTexture>>,
+}
+
+impl MissingTextures {
+	pub fn new<F: Facade>(
+		gl_ctx: &F,
+		textures: &mut Textures<Texture>,
+	) -> Result<Self, Box<dyn Error>> {
+		// A magenta and black checkerboard, so that it's obvious something is wrong.
+		let mut data = Vec::new();
+		for y in 0..PLACEHOLDER_SIZE {
+			for x in 0..PLACEHOLDER_SIZE {
+				let magenta = (x < PLACEHOLDER_SIZE / 2) == (y < PLACEHOLDER_SIZE / 2);
+				data.extend(if magenta {
+					[255, 0, 255, 255]
+				} else {
+					[0, 0, 0, 255]
+				});
+			}
+		}
+		Ok(Self {
+			placeholder: register_texture_rgba(
+				gl_ctx,
+				textures,
+				data,
+				PLACEHOLDER_SIZE,
+				PLACEHOLDER_SIZE,
+			)?,
+			missing: RefCell::new(BTreeSet::new()),
+		})
+	}
+
+	fn record(&self, missing: MissingTexture) {
+		if !self.missing.borrow().contains(&missing) {
+			eprintln!("{missing} is missing; drawing a placeholder instead.");
+			self.missing.borrow_mut().insert(missing);
+		}
+	}
+
+	pub fn tile(&self, texture_atlas: &TileAtlas, tile: usize) -> TextureRegion {
+		match texture_atlas.get(tile) {
+			Some(region) => region,
+			None => {
+				self.record(MissingTexture::Tile(tile));
+				TextureRegion::whole(self.placeholder)
+			}
+		}
+	}
+
+	pub fn icon(&self, class_icons: &HashMap<PathBuf, TextureId>, path: &Path) -> TextureId {
+		match class_icons.get(path) {
+			Some(texture) => *texture,
+			None => {
+				self.record(MissingTexture::Icon(path.to_path_buf()));
+				self.placeholder
+			}
+		}
+	}
+
+	/// Forgets missing tiles which are now in the tileset, such as after it's reloaded.
+	pub fn forget_tiles(&self, tile_count: usize) {
+		self.missing
+			.borrow_mut()
+			.retain(|missing| !matches!(missing, MissingTexture::Tile(tile) if *tile < tile_count));
+	}
+
+	/// Forgets missing icons which have now been loaded, such as after a directory is reloaded.
+	pub fn forget_icons(&self, icons: &HashMap<PathBuf, TextureId>) {
+		self.missing.borrow_mut().retain(
+			|missing| !matches!(missing, MissingTexture::Icon(path) if icons.contains_key(path)),
+		);
+	}
+
+	/// Every texture which has been missing so far.
+	pub fn occurrences(&self) -> Vec<MissingTexture> {
+		self.missing.borrow().iter().cloned().collect()
+	}
+}
+
+/// Part of a texture, such as a single tile of the tileset.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureRegion {
+	pub texture: TextureId,
+	pub uv_min: [f32; 2],
+	pub uv_max: [f32; 2],
+}
+
+impl TextureRegion {
+	pub fn whole(texture: TextureId) -> Self {
+		Self {
+			texture,
+			uv_min: [0.0, 0.0],
+			uv_max: [1.0, 1.0],
+		}
+	}
+
+	/// Adds the region to a draw list. Call `build` on the result to finish drawing it.
+	pub fn draw<'ui>(
+		&self,
+		draw_list: &'ui DrawListMut<'ui>,
+		p_min: [f32; 2],
+		p_max: [f32; 2],
+	) -> imgui::draw_list::Image<'ui> {
+		draw_list
+			.add_image(self.texture, p_min, p_max)
+			.uv_min(self.uv_min)
+			.uv_max(self.uv_max)
+	}
+
+	/// Draws the region as a widget.
+	pub fn image(&self, ui: &Ui, size: [f32; 2]) {
+		Image::new(self.texture, size)
+			.uv0(self.uv_min)
+			.uv1(self.uv_max)
+			.build(ui);
+	}
+}
+
+/// A tileset uploaded as a single texture, so that drawing many tiles doesn't switch textures.
+pub struct TileAtlas {
+	pub texture: TextureId,
+	tiles: Vec<TextureRegion>,
+}
+
+impl TileAtlas {
+	pub fn len(&self) -> usize {
+		self.tiles.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.tiles.is_empty()
+	}
+
+	pub fn get(&self, tile: usize) -> Option<TextureRegion> {
+		self.tiles.get(tile).copied()
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = &TextureRegion> {
+		self.tiles.iter()
+	}
+}
+
+impl Index<usize> for TileAtlas {
+	type Output = TextureRegion;
+
+	fn index(&self, tile: usize) -> &TextureRegion {
+		&self.tiles[tile]
+	}
+}
 
 pub fn register_tileset<F, I: GenericImageView<Pixel = Rgba<u8>>>(
 	gl_ctx: &F,
 	textures: &mut Textures<Texture>,
 	image: &I,
-) -> Result<Vec<TextureId>, Box<dyn Error>>
+	layout: &TilesetLayout,
+) -> Result<TileAtlas, Box<dyn Error>>
 where
 	F: Facade,
 {
-	let mut ids = Vec::new();
-
-	if image.width() % TILE_SIZE != 0 || image.height() % TILE_SIZE != 0 {
-		return Err(Box::from(FeError {
-			msg: format!("Image width or height is not a multiple of {TILE_SIZE}."),
-		}));
+	let (columns, rows) = layout.grid_size(image)?;
+	let texture = register_image(gl_ctx, textures, image)?;
+	// Linear minification blends in the neighbouring tiles along each tile's edges when zoomed out.
+	if let Some(texture) = textures.get_mut(texture) {
+		texture.sampler.minify_filter = MinifySamplerFilter::Nearest;
 	}
 
-	for y in (0..image.height()).step_by(TILE_SIZE as usize) {
-		for x in (0..image.width()).step_by(TILE_SIZE as usize) {
-			ids.push(register_image(
-				gl_ctx,
-				textures,
-				&*image.view(x, y, TILE_SIZE, TILE_SIZE),
-			)?);
+	let (width, height) = (image.width() as f32, image.height() as f32);
+	let mut tiles = Vec::new();
+	for row in 0..rows {
+		for column in 0..columns {
+			let x = layout.margin + column * (layout.tile_width + layout.spacing);
+			let y = layout.margin + row * (layout.tile_height + layout.spacing);
+			tiles.push(TextureRegion {
+				texture,
+				uv_min: [x as f32 / width, y as f32 / height],
+				uv_max: [
+					(x + layout.tile_width) as f32 / width,
+					(y + layout.tile_height) as f32 / height,
+				],
+			});
 		}
 	}
 
-	Ok(ids)
+	Ok(TileAtlas { texture, tiles })
+}
+
+/// Returns how many tiles a tileset has, without loading it into a texture.
+pub fn tile_count<I: GenericImageView>(
+	image: &I,
+	layout: &TilesetLayout,
+) -> Result<usize, Box<dyn Error>> {
+	let (columns, rows) = layout.grid_size(image)?;
+	Ok((columns * rows) as usize)
 }
 
 pub fn register_image<F, I: GenericImageView<Pixel = Rgba<u8>>>(
@@ -93,3 +380,52 @@ where
 
 	Ok(textures.insert(texture))
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use image::RgbaImage;
+
+	fn layout(tile: u32, margin: u32, spacing: u32) -> TilesetLayout {
+		TilesetLayout {
+			tile_width: tile,
+			tile_height: tile,
+			margin,
+			spacing,
+		}
+	}
+
+	#[test]
+	fn grid_size_of_packed_tiles() {
+		let image = RgbaImage::new(64, 32);
+		assert_eq!(layout(16, 0, 0).grid_size(&image).unwrap(), (4, 2));
+	}
+
+	#[test]
+	fn grid_size_with_margin_and_spacing() {
+		// 1 + 16 + 2 + 16 + 2 + 16 + 1
+		let image = RgbaImage::new(54, 18);
+		assert_eq!(layout(16, 1, 2).grid_size(&image).unwrap(), (3, 1));
+	}
+
+	#[test]
+	fn grid_size_rejects_leftover_pixels() {
+		let image = RgbaImage::new(40, 32);
+		assert!(layout(16, 0, 0).grid_size(&image).is_err());
+	}
+
+	#[test]
+	fn grid_size_rejects_images_smaller_than_a_tile() {
+		let image = RgbaImage::new(16, 16);
+		assert!(layout(16, 1, 0).grid_size(&image).is_err());
+	}
+
+	#[test]
+	fn grid_size_rejects_oversized_layouts() {
+		let image = RgbaImage::new(16, 16);
+		let err = layout(16, u32::MAX, 0).grid_size(&image).unwrap_err();
+		assert!(err.to_string().contains("margin"));
+		let err = layout(16, 0, u32::MAX).grid_size(&image).unwrap_err();
+		assert!(err.to_string().contains("spacing"));
+	}
+}
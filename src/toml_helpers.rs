@@ -0,0 +1,48 @@
+//! Shorthands for reading values out of hand-parsed TOML tables.
+
+use fe_data::FeError;
+use std::error::Error;
+use toml::*;
+
+pub(crate) fn get_value<'a>(table: &'a Table, key: &str) -> Result<&'a Value, Box<dyn Error>> {
+	match table.get(key) {
+		Some(value) => Ok(value),
+		None => Err(FeError::from(format!("Missing key: {key}")))?,
+	}
+}
+
+pub(crate) fn as_usize(value: &Value, key: &str) -> Result<usize, Box<dyn Error>> {
+	match value {
+		Value::Integer(i) if *i >= 0 => Ok(*i as usize),
+		_ => Err(FeError::from(format!("{key} is not a positive integer")))?,
+	}
+}
+
+pub(crate) fn get_usize(table: &Table, key: &str) -> Result<usize, Box<dyn Error>> {
+	as_usize(get_value(table, key)?, key)
+}
+
+pub(crate) fn get_string(table: &Table, key: &str) -> Result<String, Box<dyn Error>> {
+	match get_value(table, key)? {
+		Value::String(s) => Ok(s.clone()),
+		_ => Err(FeError::from(format!("{key} is not a string")))?,
+	}
+}
+
+pub(crate) fn get_array<'a>(table: &'a Table, key: &str) -> Result<&'a Vec<Value>, Box<dyn Error>> {
+	match get_value(table, key)? {
+		Value::Array(array) => Ok(array),
+		_ => Err(FeError::from(format!("{key} is not an array")))?,
+	}
+}
+
+pub(crate) fn get_table<'a>(table: &'a Table, key: &str) -> Result<&'a Table, Box<dyn Error>> {
+	match get_value(table, key)? {
+		Value::Table(table) => Ok(table),
+		_ => Err(FeError::from(format!("{key} is not a table")))?,
+	}
+}
+
+pub(crate) fn usize_value(i: usize) -> Value {
+	Value::Integer(i as i64)
+}
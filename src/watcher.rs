@@ -0,0 +1,71 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Notices when files are changed outside of the editor, such as by an artist's image editor.
+/// Modification times are polled, since watching for changes needs a different API on every platform.
+pub struct FileWatcher {
+	/// Each watched path, along with the modification times of it or the files inside it.
+	files: Vec<(PathBuf, Vec<(PathBuf, SystemTime)>)>,
+	last_poll: Instant,
+}
+
+impl FileWatcher {
+	pub fn new() -> Self {
+		Self {
+			files: Vec::new(),
+			last_poll: Instant::now(),
+		}
+	}
+
+	/// Watches a file, or every file in a directory.
+	pub fn watch(&mut self, path: impl Into<PathBuf>) {
+		let path = path.into();
+		let snapshot = snapshot(&path);
+		self.files.push((path, snapshot));
+	}
+
+	/// Returns the watched paths which have changed since they were last checked.
+	/// Paths are only checked once every `POLL_INTERVAL`.
+	pub fn poll(&mut self) -> Vec<PathBuf> {
+		let mut changed = Vec::new();
+		if self.last_poll.elapsed() < POLL_INTERVAL {
+			return changed;
+		}
+		self.last_poll = Instant::now();
+
+		for (path, old_snapshot) in &mut self.files {
+			let snapshot = snapshot(path);
+			if snapshot != *old_snapshot {
+				*old_snapshot = snapshot;
+				changed.push(path.clone());
+			}
+		}
+		changed
+	}
+}
+
+/// Lists the modification time of a file, or of every file in a directory.
+/// Missing files are left out, so that deleting or creating one counts as a change.
+fn snapshot(path: &Path) -> Vec<(PathBuf, SystemTime)> {
+	let modified = |path: PathBuf| {
+		let time = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+		Some((path, time))
+	};
+
+	let mut files = Vec::new();
+	if path.is_dir() {
+		if let Ok(dir) = fs::read_dir(path) {
+			files.extend(
+				dir.filter_map(|e| e.ok())
+					.filter_map(|e| modified(e.path())),
+			);
+		}
+		files.sort();
+	} else {
+		files.extend(modified(path.to_path_buf()));
+	}
+	files
+}